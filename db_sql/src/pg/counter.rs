@@ -0,0 +1,37 @@
+//! Event counters and clock values are u128 in Rust and on the wire, stored
+//! as `numeric(39, 0)`, which holds every u128. Reading a stored counter back
+//! is checked, anything that is not a u128 is an error.
+
+use sea_orm::entity::prelude::BigDecimal;
+use sea_orm::DbErr;
+use std::str::FromStr;
+
+/// Decimal digits of u128::MAX.
+pub const COUNTER_PRECISION: u32 = 39;
+
+pub fn to_numeric(counter: u128) -> BigDecimal {
+    BigDecimal::from_str(&counter.to_string()).expect("u128 digits are a valid decimal")
+}
+
+pub fn from_numeric(value: &BigDecimal) -> Result<u128, DbErr> {
+    if !value.is_integer() {
+        return Err(DbErr::Type(format!("counter {} is not an integer", value)));
+    }
+    value.with_scale(0).to_string().parse::<u128>()
+        .map_err(|err| DbErr::Type(format!("counter {} out of u128 range: {}", value, err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numeric_counter_roundtrip() {
+        for counter in [0, 1, u128::from(u64::MAX) + 1, u128::MAX] {
+            assert_eq!(from_numeric(&to_numeric(counter)).unwrap(), counter);
+        }
+        assert!(from_numeric(&BigDecimal::from(-1)).is_err());
+        assert!(from_numeric(&BigDecimal::from_str("1.5").unwrap()).is_err());
+        assert!(from_numeric(&(to_numeric(u128::MAX) + BigDecimal::from(1))).is_err());
+    }
+}
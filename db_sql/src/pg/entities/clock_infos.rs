@@ -14,8 +14,14 @@ pub struct Model {
     pub message_id: String,
     #[sea_orm(column_type = "Binary(BlobSize::Blob(None))")]
     pub raw_message: Vec<u8>,
-    pub event_count: i64,
+    #[sea_orm(column_type = "Decimal(Some((39, 0)))")]
+    pub event_count: BigDecimal,
     pub create_at: Option<DateTime>,
+    pub prev_clock_hash: Option<String>,
+    pub signature: Option<String>,
+    pub hlc_physical: Option<i64>,
+    pub hlc_logical: Option<i32>,
+    pub sign_version: Option<i32>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -9,8 +9,10 @@ pub struct Model {
     pub id: i64,
     pub from_id: String,
     pub to_id: String,
-    pub start_count: i64,
-    pub end_count: i64,
+    #[sea_orm(column_type = "Decimal(Some((39, 0)))")]
+    pub start_count: BigDecimal,
+    #[sea_orm(column_type = "Decimal(Some((39, 0)))")]
+    pub end_count: BigDecimal,
     pub s_clock_hash: String,
     pub e_clock_hash: String,
     pub merge_at: DateTime,
@@ -5,4 +5,6 @@ pub mod prelude;
 pub mod bussiness_clocks;
 pub mod clock_infos;
 pub mod merge_logs;
+pub mod node_dictionary;
+pub mod peers;
 pub mod z_messages;
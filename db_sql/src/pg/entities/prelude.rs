@@ -3,4 +3,6 @@
 pub use super::bussiness_clocks::Entity as BussinessClocks;
 pub use super::clock_infos::Entity as ClockInfos;
 pub use super::merge_logs::Entity as MergeLogs;
+pub use super::node_dictionary::Entity as NodeDictionary;
+pub use super::peers::Entity as Peers;
 pub use super::z_messages::Entity as ZMessages;
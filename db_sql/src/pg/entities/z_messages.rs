@@ -17,6 +17,12 @@ pub struct Model {
     pub signature: Option<Vec<u8>>,
     pub from: String,
     pub to: String,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub origin_clock: Option<String>,
+    #[sea_orm(column_type = "Decimal(Some((39, 0)))", nullable)]
+    pub order_events: Option<BigDecimal>,
+    pub order_hash: Option<String>,
+    pub signature_scheme: i32,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
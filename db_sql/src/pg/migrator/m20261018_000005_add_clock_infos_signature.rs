@@ -0,0 +1,45 @@
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m_20261018_000005_add_clock_infos_signature"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Add the signed clock chain columns to clock_infos.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ClockInfos::Table)
+                    .add_column(ColumnDef::new(ClockInfos::PrevClockHash).char_len(64))
+                    .add_column(ColumnDef::new(ClockInfos::Signature).char_len(128))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    // Define how to rollback this migration: Drop the signed clock chain columns.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ClockInfos::Table)
+                    .drop_column(ClockInfos::PrevClockHash)
+                    .drop_column(ClockInfos::Signature)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(Iden)]
+pub enum ClockInfos {
+    Table,
+    PrevClockHash,
+    Signature,
+}
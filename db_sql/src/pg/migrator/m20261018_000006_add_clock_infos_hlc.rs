@@ -0,0 +1,45 @@
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m_20261018_000006_add_clock_infos_hlc"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Add the hybrid logical clock columns to clock_infos.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ClockInfos::Table)
+                    .add_column(ColumnDef::new(ClockInfos::HlcPhysical).big_integer())
+                    .add_column(ColumnDef::new(ClockInfos::HlcLogical).integer())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    // Define how to rollback this migration: Drop the hybrid logical clock columns.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ClockInfos::Table)
+                    .drop_column(ClockInfos::HlcPhysical)
+                    .drop_column(ClockInfos::HlcLogical)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(Iden)]
+pub enum ClockInfos {
+    Table,
+    HlcPhysical,
+    HlcLogical,
+}
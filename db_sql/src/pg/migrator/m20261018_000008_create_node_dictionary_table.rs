@@ -0,0 +1,46 @@
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m_20261018_000008_create_node_dictionary_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Create the node_dictionary table,
+    // mapping node ids to the small indices of compact clocks.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(NodeDictionary::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(NodeDictionary::NodeIndex)
+                            .integer()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(NodeDictionary::NodeId).char_len(64).not_null().unique_key())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    // Define how to rollback this migration: Drop the node_dictionary table.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(NodeDictionary::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+pub enum NodeDictionary {
+    Table,
+    NodeIndex,
+    NodeId,
+}
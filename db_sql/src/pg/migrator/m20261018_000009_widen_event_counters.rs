@@ -0,0 +1,69 @@
+use sea_orm_migration::prelude::*;
+
+use crate::pg::counter::COUNTER_PRECISION;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m_20261018_000009_widen_event_counters"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Widen the event counters to numeric, which holds every u128.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ClockInfos::Table)
+                    .modify_column(ColumnDef::new(ClockInfos::EventCount).decimal_len(COUNTER_PRECISION, 0).not_null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(MergeLogs::Table)
+                    .modify_column(ColumnDef::new(MergeLogs::StartCount).decimal_len(COUNTER_PRECISION, 0).not_null())
+                    .modify_column(ColumnDef::new(MergeLogs::EndCount).decimal_len(COUNTER_PRECISION, 0).not_null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    // Define how to rollback this migration: Narrow the event counters back to bigint.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ClockInfos::Table)
+                    .modify_column(ColumnDef::new(ClockInfos::EventCount).big_integer().not_null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(MergeLogs::Table)
+                    .modify_column(ColumnDef::new(MergeLogs::StartCount).big_integer().not_null())
+                    .modify_column(ColumnDef::new(MergeLogs::EndCount).big_integer().not_null())
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(Iden)]
+pub enum ClockInfos {
+    Table,
+    EventCount,
+}
+
+#[derive(Iden)]
+pub enum MergeLogs {
+    Table,
+    StartCount,
+    EndCount,
+}
@@ -0,0 +1,52 @@
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m_20261018_000010_create_peers_table"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Create the peers table,
+    // the membership registry of the known server nodes.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Peers::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Peers::NodeId)
+                            .char_len(64)
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Peers::Address).string().not_null())
+                    .col(ColumnDef::new(Peers::PublicKey).char_len(64).not_null())
+                    .col(ColumnDef::new(Peers::LastHeartbeat).timestamp())
+                    .col(ColumnDef::new(Peers::LastClock).text())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    // Define how to rollback this migration: Drop the peers table.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Peers::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+pub enum Peers {
+    Table,
+    NodeId,
+    Address,
+    PublicKey,
+    LastHeartbeat,
+    LastClock,
+}
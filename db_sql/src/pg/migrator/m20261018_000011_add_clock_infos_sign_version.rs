@@ -0,0 +1,55 @@
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m_20261018_000011_add_clock_infos_sign_version"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Add the signing payload version to clock_infos,
+    // rows stored before it are v2 with a hybrid clock, v1 without.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ClockInfos::Table)
+                    .add_column(ColumnDef::new(ClockInfos::SignVersion).integer())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(ClockInfos::Table)
+                    .value(
+                        ClockInfos::SignVersion,
+                        Expr::case(Expr::col(ClockInfos::HlcPhysical).is_null(), 1).finally(2),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    // Define how to rollback this migration: Drop the signing payload version column.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ClockInfos::Table)
+                    .drop_column(ClockInfos::SignVersion)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(Iden)]
+pub enum ClockInfos {
+    Table,
+    HlcPhysical,
+    SignVersion,
+}
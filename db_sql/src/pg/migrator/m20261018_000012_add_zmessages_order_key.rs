@@ -0,0 +1,63 @@
+use sea_orm_migration::prelude::*;
+use sea_query::Index;
+
+use crate::pg::counter::COUNTER_PRECISION;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m_20261018_000012_add_zmessages_order_key"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Add the total order key of the origin clock to z_messages,
+    // existing rows are filled in by the node on start.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ZMessages::Table)
+                    .add_column(ColumnDef::new(ZMessages::OrderEvents).decimal_len(COUNTER_PRECISION, 0))
+                    .add_column(ColumnDef::new(ZMessages::OrderHash).string())
+                    .to_owned(),
+            )
+            .await?;
+
+        let order_index = Index::create()
+            .if_not_exists()
+            .name("idx-zmessages-total-order")
+            .table(ZMessages::Table)
+            .col(ZMessages::OrderEvents)
+            .col(ZMessages::OrderHash)
+            .col(ZMessages::MessageId)
+            .to_owned();
+        manager.create_index(order_index).await
+    }
+
+    // Define how to rollback this migration: Drop the total order key.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(Index::drop().name("idx-zmessages-total-order").table(ZMessages::Table).to_owned())
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ZMessages::Table)
+                    .drop_column(ZMessages::OrderEvents)
+                    .drop_column(ZMessages::OrderHash)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(Iden)]
+pub enum ZMessages {
+    Table,
+    MessageId,
+    OrderEvents,
+    OrderHash,
+}
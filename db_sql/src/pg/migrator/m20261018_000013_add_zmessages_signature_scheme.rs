@@ -0,0 +1,43 @@
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m_20261018_000013_add_zmessages_signature_scheme"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    // Define how to apply this migration: Add the signature scheme to z_messages, the message id
+    // covers it. Rows stored before it used the default scheme 0.
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ZMessages::Table)
+                    .add_column(ColumnDef::new(ZMessages::SignatureScheme).integer().not_null().default(0))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    // Define how to rollback this migration: Drop the signature scheme column.
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ZMessages::Table)
+                    .drop_column(ZMessages::SignatureScheme)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(Iden)]
+pub enum ZMessages {
+    Table,
+    SignatureScheme,
+}
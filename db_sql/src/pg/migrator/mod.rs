@@ -4,6 +4,15 @@ mod m20240428_000001_create_clock_infos_table;
 mod m20240428_000002_create_merge_logs_table;
 mod m20240517_000003_create_zmessages_table;
 mod m20240529_000004_create_business_clocks_table;
+mod m20261018_000005_add_clock_infos_signature;
+mod m20261018_000006_add_clock_infos_hlc;
+mod m20261018_000007_add_zmessages_origin_clock;
+mod m20261018_000008_create_node_dictionary_table;
+mod m20261018_000009_widen_event_counters;
+mod m20261018_000010_create_peers_table;
+mod m20261018_000011_add_clock_infos_sign_version;
+mod m20261018_000012_add_zmessages_order_key;
+mod m20261018_000013_add_zmessages_signature_scheme;
 
 /// Use the sea-orm-cli to generate data entity, 
 /// command like as follow:
@@ -24,6 +33,15 @@ impl MigratorTrait for Migrator {
             Box::new(m20240428_000002_create_merge_logs_table::Migration),
             Box::new(m20240517_000003_create_zmessages_table::Migration),
             Box::new(m20240529_000004_create_business_clocks_table::Migration),
+            Box::new(m20261018_000005_add_clock_infos_signature::Migration),
+            Box::new(m20261018_000006_add_clock_infos_hlc::Migration),
+            Box::new(m20261018_000007_add_zmessages_origin_clock::Migration),
+            Box::new(m20261018_000008_create_node_dictionary_table::Migration),
+            Box::new(m20261018_000009_widen_event_counters::Migration),
+            Box::new(m20261018_000010_create_peers_table::Migration),
+            Box::new(m20261018_000011_add_clock_infos_sign_version::Migration),
+            Box::new(m20261018_000012_add_zmessages_order_key::Migration),
+            Box::new(m20261018_000013_add_zmessages_signature_scheme::Migration),
         ]
     }
 }
\ No newline at end of file
@@ -1,3 +1,4 @@
+pub mod counter;
 pub mod entities;
 pub mod pg_client;
 pub mod migrator;
\ No newline at end of file
@@ -16,6 +16,36 @@ pub enum ZchronodConfigError {
 
     #[error("Error nodeid illegal, must be hex format, and 64 bits")]
     IllegalNodeId,
+
+    #[error("Error secret_key illegal, must be the hex secp256k1 secret key of nodeid")]
+    IllegalSecretKey,
+
+    #[error("Error clock epoch illegal at node {0}, must be another hex node id retired once with a non-zero final count")]
+    IllegalClockEpoch(String),
+
+    #[error("Error sync_batch_maximum must be at least 1")]
+    IllegalSyncBatchMaximum,
+
+    #[error("Error direct push mode needs the outer_p2p address")]
+    MissingOuterP2p,
+
+    #[error("Error quic_cert and quic_key must be set together, the quic transport requires both")]
+    IllegalQuicCert,
+
+    #[error("Error signature policy for unknown message type {0}, must be a ZType name like zchat")]
+    IllegalSignaturePolicy(String),
+
+    #[error("Error max_message_size {0} illegal, must be from 1 to 4294967295 bytes")]
+    IllegalMaxMessageSize(usize),
+
+    #[error("Error inner_socket_mode {0:#o} illegal, must be permission bits without access for others")]
+    IllegalSocketMode(u32),
+
+    #[error("Error endorsement signer {0} illegal, must be a hex node id")]
+    IllegalEndorsementSigner(String),
+
+    #[error("Error endorsement_threshold {0} illegal, must be 0 or 1 and not exceed the number of configured endorsement_signers")]
+    IllegalEndorsementThreshold(usize),
 }
 
 
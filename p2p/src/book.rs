@@ -0,0 +1,154 @@
+//! Peer address book with reconnect backoff.
+//!
+//! Relayed peers all share the relay address, so liveness is tracked per node
+//! id: the owner calls [`AddressBook::seen`] once it verified a message of the
+//! node. A node silent for longer than the peer timeout is due for a reconnect
+//! attempt, retried with exponential backoff until it is seen again.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ReconnectPolicy {
+    pub peer_timeout: u128,     // milliseconds of silence before a node counts as disconnected, 0 never
+    pub backoff_base: u128,     // milliseconds before the first reconnect attempt
+    pub backoff_maximum: u128,  // cap of the doubled backoff
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            peer_timeout: 30_000,
+            backoff_base: 1_000,
+            backoff_maximum: 60_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct PeerEntry {
+    address: SocketAddr,
+    last_seen: u128,
+    backoff: u128,      // 0 while connected
+    next_retry: u128,
+}
+
+#[derive(Debug, Default)]
+pub struct AddressBook {
+    policy: ReconnectPolicy,
+    peers: HashMap<String, PeerEntry>,
+}
+
+impl AddressBook {
+    pub fn new(policy: ReconnectPolicy) -> Self {
+        Self { policy, peers: HashMap::new() }
+    }
+
+    /// Record the address of a node, a new or moved node counts as just seen.
+    pub fn insert(&mut self, node_id: &str, address: SocketAddr, now: u128) {
+        match self.peers.get_mut(node_id) {
+            Some(entry) if entry.address == address => {}
+            Some(entry) => {
+                entry.address = address;
+                Self::reset(entry, now);
+            }
+            None => {
+                self.peers.insert(node_id.to_owned(), PeerEntry { address, last_seen: now, backoff: 0, next_retry: 0 });
+            }
+        }
+    }
+
+    /// Mark a node alive, which ends its reconnect backoff.
+    pub fn seen(&mut self, node_id: &str, now: u128) {
+        if let Some(entry) = self.peers.get_mut(node_id) {
+            Self::reset(entry, now);
+        }
+    }
+
+    pub fn remove(&mut self, node_id: &str) -> Option<SocketAddr> {
+        self.peers.remove(node_id).map(|entry| entry.address)
+    }
+
+    pub fn lookup(&self, node_id: &str) -> Option<SocketAddr> {
+        self.peers.get(node_id).map(|entry| entry.address)
+    }
+
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+
+    /// Distinct addresses of all known nodes.
+    pub fn addresses(&self) -> Vec<SocketAddr> {
+        let mut addresses: Vec<SocketAddr> = self.peers.values().map(|entry| entry.address).collect();
+        addresses.sort();
+        addresses.dedup();
+        addresses
+    }
+
+    /// Disconnected nodes whose next attempt is due, each one's backoff is
+    /// doubled for the attempt after.
+    pub fn due_reconnects(&mut self, now: u128) -> Vec<(String, SocketAddr)> {
+        if self.policy.peer_timeout == 0 {
+            return Vec::new();
+        }
+        let policy = self.policy;
+        let mut due = Vec::new();
+        for (node_id, entry) in self.peers.iter_mut() {
+            if now.saturating_sub(entry.last_seen) <= policy.peer_timeout {
+                continue;
+            }
+            if entry.backoff == 0 {
+                entry.backoff = policy.backoff_base.max(1);
+                entry.next_retry = now;
+            }
+            if entry.next_retry <= now {
+                due.push((node_id.clone(), entry.address));
+                entry.next_retry = now + entry.backoff;
+                entry.backoff = (entry.backoff * 2).min(policy.backoff_maximum.max(1));
+            }
+        }
+        due.sort();
+        due
+    }
+
+    fn reset(entry: &mut PeerEntry, now: u128) {
+        entry.last_seen = now;
+        entry.backoff = 0;
+        entry.next_retry = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reconnect_backoff() {
+        let policy = ReconnectPolicy { peer_timeout: 100, backoff_base: 10, backoff_maximum: 40 };
+        let mut book = AddressBook::new(policy);
+        let relay: SocketAddr = "127.0.0.1:8050".parse().unwrap();
+        book.insert("a", relay, 0);
+        book.insert("b", relay, 0);
+        assert_eq!(book.addresses(), vec![relay]);
+
+        book.seen("b", 90);
+        assert!(book.due_reconnects(100).is_empty());
+        assert_eq!(book.due_reconnects(101), vec![("a".to_owned(), relay)]);
+        // retried after 10, 20, then capped at 40
+        assert!(book.due_reconnects(110).is_empty());
+        assert_eq!(book.due_reconnects(111).len(), 1);
+        assert!(book.due_reconnects(130).is_empty());
+        assert_eq!(book.due_reconnects(131).len(), 1);
+        assert_eq!(book.due_reconnects(171), vec![("a".to_owned(), relay)]);
+        assert_eq!(book.due_reconnects(191), vec![("b".to_owned(), relay)]);
+        assert_eq!(book.due_reconnects(211), vec![("a".to_owned(), relay), ("b".to_owned(), relay)]);
+
+        book.seen("a", 212);
+        assert!(book.due_reconnects(230).is_empty());
+        assert_eq!(book.due_reconnects(231), vec![("b".to_owned(), relay)]);
+    }
+}
@@ -0,0 +1,21 @@
+use thiserror::Error;
+
+pub type P2pResult<T> = Result<T, P2pError>;
+
+#[derive(Error, Debug)]
+pub enum P2pError {
+    #[error("Error while performing IO on the p2p socket: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("Error frame is not an innermsg: {0}")]
+    DecodeError(#[from] prost::DecodeError),
+
+    #[error("Error frame of {0} bytes exceeds the maximum frame size")]
+    Oversize(usize),
+
+    #[error("Error no address known for node {0}")]
+    UnknownPeer(String),
+
+    #[error("Error on the quic transport: {0}")]
+    QuicError(String),
+}
@@ -0,0 +1,310 @@
+//! Fragmentation of frames larger than a datagram.
+//!
+//! Each piece is a [`Fragment`] after the [`FRAGMENT_MARK`] byte. The receiver
+//! collects the pieces per sender and message id, and drops a message that
+//! exceeds the size limit or is still incomplete after the reassembly timeout.
+//! Pieces are buffered as they arrive, and the number of incomplete messages
+//! and their bytes are capped, the oldest one is dropped to make room.
+
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use prost::Message;
+use protos::innermsg::Fragment;
+use tracing::*;
+
+/// First byte of a fragment datagram. Tag bytes 0x00 to 0x07 carry field
+/// number 0, which is invalid in protobuf, so no Innermsg starts with one.
+pub const FRAGMENT_MARK: u8 = 0;
+
+/// Upper bound of the mark byte plus the encoded fragment fields besides data.
+const FRAGMENT_OVERHEAD: usize = 64;
+
+/// Smallest data of a fragment other than the last.
+const MIN_FRAGMENT_DATA: usize = 64;
+
+/// Bytes of incomplete messages held in total, or one message of the size limit if larger.
+const MAX_BUFFERED: usize = 64 << 20;
+
+/// Incomplete messages held per sender.
+const MAX_PARTIALS_PER_SRC: usize = 8;
+
+/// Incomplete messages held in total.
+const MAX_PARTIALS: usize = 256;
+
+/// Split `frame` into fragment datagrams of at most `max_frame` bytes.
+pub fn split(message_id: u64, frame: &[u8], max_frame: usize) -> Vec<Vec<u8>> {
+    let chunk_size = chunk_size(max_frame);
+    let count = frame.len().div_ceil(chunk_size) as u32;
+    frame.chunks(chunk_size).enumerate()
+        .map(|(index, chunk)| {
+            let fragment = Fragment {
+                message_id,
+                index: index as u32,
+                count,
+                total_len: frame.len() as u32,
+                data: chunk.to_vec(),
+            };
+            let mut datagram = Vec::with_capacity(1 + fragment.encoded_len());
+            datagram.push(FRAGMENT_MARK);
+            fragment.encode(&mut datagram).expect("vec grows as needed");
+            datagram
+        })
+        .collect()
+}
+
+/// Data carried by each fragment but the last one, for datagrams of at most `max_frame` bytes.
+fn chunk_size(max_frame: usize) -> usize {
+    max_frame.saturating_sub(FRAGMENT_OVERHEAD).max(MIN_FRAGMENT_DATA)
+}
+
+/// The fragment of a datagram starting with [`FRAGMENT_MARK`], None for a whole frame.
+pub fn parse(datagram: &[u8]) -> Option<Result<Fragment, prost::DecodeError>> {
+    match datagram.split_first() {
+        Some((&FRAGMENT_MARK, rest)) => Some(Fragment::decode(rest)),
+        _ => None,
+    }
+}
+
+struct Partial {
+    started: Instant,
+    total_len: usize,
+    parts: BTreeMap<usize, Vec<u8>>,  // by index, filled as the pieces arrive
+}
+
+pub struct Reassembler {
+    max_message_size: usize,
+    timeout: Duration,
+    partials: HashMap<(SocketAddr, u64), Partial>,
+    max_buffered: usize,
+    buffered: usize,    // bytes of all partials
+}
+
+impl Reassembler {
+    pub fn new(max_message_size: usize, timeout: Duration) -> Self {
+        let max_buffered = max_message_size.max(MAX_BUFFERED);
+        Self { max_message_size, timeout, partials: HashMap::new(), max_buffered, buffered: 0 }
+    }
+
+    /// Add a fragment from `src` split for datagrams of `max_frame` bytes,
+    /// returns the whole frame once its last piece arrived.
+    pub fn insert(&mut self, src: SocketAddr, fragment: Fragment, max_frame: usize, now: Instant) -> Option<Vec<u8>> {
+        self.expire(now);
+        let total_len = fragment.total_len as usize;
+        let index = fragment.index as usize;
+        if total_len > self.max_message_size {
+            warn!("Drop message {} from {}, {} bytes exceed the limit {}", fragment.message_id, src, total_len, self.max_message_size);
+            return None;
+        }
+        // the sender cut the frame into chunks of the link's frame size
+        let chunk_size = chunk_size(max_frame);
+        let count = total_len.div_ceil(chunk_size);
+        if fragment.count as usize != count || index >= count {
+            warn!("Drop fragment {}/{} of message {} from {}, bad index", index, fragment.count, fragment.message_id, src);
+            return None;
+        }
+        if fragment.data.len() != chunk_size.min(total_len - index * chunk_size) {
+            warn!("Drop fragment {}/{} of message {} from {}, bad length", index, count, fragment.message_id, src);
+            return None;
+        }
+        let key = (src, fragment.message_id);
+        if !self.partials.contains_key(&key) {
+            self.make_room(src);
+        }
+        let partial = self.partials.entry(key).or_insert_with(|| Partial { started: now, total_len, parts: BTreeMap::new() });
+        if partial.total_len != total_len {
+            warn!("Drop message {} from {}, fragments disagree on its size", fragment.message_id, src);
+            self.remove(&key);
+            return None;
+        }
+        if partial.parts.contains_key(&index) {
+            return None;
+        }
+        let len = fragment.data.len();
+        partial.parts.insert(index, fragment.data);
+        self.buffered += len;
+        if self.buffered > self.max_buffered && !self.evict_oldest_except(key) {
+            warn!("Drop message {} from {}, fragment buffer is full", fragment.message_id, src);
+            self.remove(&key);
+            return None;
+        }
+        if self.partials.get(&key)?.parts.len() < count {
+            return None;
+        }
+        let partial = self.remove(&key)?;
+        Some(partial.parts.into_values().flatten().collect())
+    }
+
+    /// Number of incomplete messages held.
+    pub fn pending(&self) -> usize {
+        self.partials.len()
+    }
+
+    /// Drop the oldest incomplete messages until a new one from `src` fits the caps.
+    fn make_room(&mut self, src: SocketAddr) {
+        let from_src = self.partials.keys().filter(|(from, _)| *from == src).count();
+        if from_src >= MAX_PARTIALS_PER_SRC {
+            self.evict_oldest(|from| from == src);
+        }
+        if self.partials.len() >= MAX_PARTIALS {
+            self.evict_oldest(|_| true);
+        }
+    }
+
+    fn evict_oldest(&mut self, filter: impl Fn(SocketAddr) -> bool) {
+        let oldest = self.partials.iter()
+            .filter(|((from, _), _)| filter(*from))
+            .min_by_key(|(_, partial)| partial.started)
+            .map(|(key, _)| *key);
+        if let Some((src, message_id)) = oldest {
+            warn!("Drop message {} from {}, too many incomplete messages", message_id, src);
+            self.remove(&(src, message_id));
+        }
+    }
+
+    /// Drop the oldest incomplete messages but `keep` until the buffered bytes
+    /// fit the cap again, returns false if they don't without `keep` either.
+    fn evict_oldest_except(&mut self, keep: (SocketAddr, u64)) -> bool {
+        while self.buffered > self.max_buffered {
+            let oldest = self.partials.iter()
+                .filter(|(key, _)| **key != keep)
+                .min_by_key(|(_, partial)| partial.started)
+                .map(|(key, _)| *key);
+            let Some((src, message_id)) = oldest else {
+                return false;
+            };
+            warn!("Drop message {} from {}, fragment buffer is full", message_id, src);
+            self.remove(&(src, message_id));
+        }
+        true
+    }
+
+    fn remove(&mut self, key: &(SocketAddr, u64)) -> Option<Partial> {
+        let partial = self.partials.remove(key)?;
+        self.buffered -= partial.parts.values().map(Vec::len).sum::<usize>();
+        Some(partial)
+    }
+
+    fn expire(&mut self, now: Instant) {
+        let timeout = self.timeout;
+        let expired: Vec<_> = self.partials.iter()
+            .filter(|(_, partial)| now.saturating_duration_since(partial.started) > timeout)
+            .map(|(key, _)| *key)
+            .collect();
+        for (src, message_id) in expired {
+            warn!("Drop message {} from {}, reassembly timed out", message_id, src);
+            self.remove(&(src, message_id));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fragments(datagrams: &[Vec<u8>]) -> Vec<Fragment> {
+        datagrams.iter().map(|datagram| parse(datagram).unwrap().unwrap()).collect()
+    }
+
+    #[test]
+    fn split_and_reassemble() {
+        let frame: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+        let datagrams = split(7, &frame, 164);
+        assert_eq!(datagrams.len(), 10);
+        assert!(datagrams.iter().all(|datagram| datagram.len() <= 164));
+        assert!(parse(&[0x08, 0x01]).is_none());
+
+        let src: SocketAddr = "127.0.0.1:8050".parse().unwrap();
+        let now = Instant::now();
+        let mut reassembler = Reassembler::new(1000, Duration::from_secs(1));
+        let mut pieces = fragments(&datagrams);
+        pieces.reverse();
+        let last = pieces.pop().unwrap();
+        for piece in pieces {
+            assert!(reassembler.insert(src, piece, 164, now).is_none());
+        }
+        assert_eq!(reassembler.insert(src, last, 164, now), Some(frame.clone()));
+        assert_eq!(reassembler.pending(), 0);
+
+        // over the size limit
+        let mut small = Reassembler::new(999, Duration::from_secs(1));
+        assert!(fragments(&datagrams).into_iter().all(|piece| small.insert(src, piece, 164, now).is_none()));
+        assert_eq!(small.pending(), 0);
+
+        // a missing piece times out
+        let mut pieces = fragments(&datagrams);
+        pieces.pop();
+        for piece in pieces {
+            reassembler.insert(src, piece, 164, now);
+        }
+        assert_eq!(reassembler.pending(), 1);
+        let later = now + Duration::from_secs(2);
+        let other = fragments(&split(8, &frame[..10], 164)).pop().unwrap();
+        assert_eq!(reassembler.insert(src, other, 164, later), Some(frame[..10].to_vec()));
+        assert_eq!(reassembler.pending(), 0);
+
+        // more pieces than the size split at the frame size gives
+        let mut bogus = fragments(&split(9, &frame[..100], 164)).pop().unwrap();
+        bogus.count = 1 << 30;
+        assert!(reassembler.insert(src, bogus.clone(), 164, later).is_none());
+        bogus.count = 2;
+        assert!(reassembler.insert(src, bogus, 164, later).is_none());
+        // a piece shorter than its chunk
+        let mut short = fragments(&split(10, &frame, 164)).swap_remove(0);
+        short.data.truncate(10);
+        assert!(reassembler.insert(src, short, 164, later).is_none());
+        assert_eq!(reassembler.pending(), 0);
+    }
+
+    #[test]
+    fn caps_incomplete_messages() {
+        let frame = vec![1u8; 1000];
+        let now = Instant::now();
+        let mut reassembler = Reassembler::new(1000, Duration::from_secs(10));
+        let first_piece = |message_id: u64| fragments(&split(message_id, &frame, 164)).swap_remove(0);
+
+        let src: SocketAddr = "127.0.0.1:8050".parse().unwrap();
+        for message_id in 0..MAX_PARTIALS_PER_SRC as u64 + 2 {
+            let started = now + Duration::from_millis(message_id);
+            assert!(reassembler.insert(src, first_piece(message_id), 164, started).is_none());
+        }
+        assert_eq!(reassembler.pending(), MAX_PARTIALS_PER_SRC);
+        // the oldest ones made room
+        assert!(!reassembler.partials.contains_key(&(src, 0)));
+        assert!(!reassembler.partials.contains_key(&(src, 1)));
+
+        for port in 0..MAX_PARTIALS as u16 {
+            let other = SocketAddr::from(([127, 0, 0, 2], port));
+            reassembler.insert(other, first_piece(0), 164, now + Duration::from_secs(1));
+        }
+        assert_eq!(reassembler.pending(), MAX_PARTIALS);
+        assert!(reassembler.partials.keys().all(|(from, _)| *from != src));
+        assert_eq!(reassembler.buffered, MAX_PARTIALS * 100);
+    }
+
+    #[test]
+    fn caps_buffered_bytes() {
+        let frame = vec![1u8; 1000];
+        let now = Instant::now();
+        let mut reassembler = Reassembler::new(1000, Duration::from_secs(10));
+        reassembler.max_buffered = 250;
+        let src: SocketAddr = "127.0.0.1:8050".parse().unwrap();
+        for message_id in 0..3 {
+            let piece = fragments(&split(message_id, &frame, 164)).swap_remove(0);
+            assert!(reassembler.insert(src, piece, 164, now + Duration::from_millis(message_id)).is_none());
+        }
+        // the oldest message made room for the third
+        assert_eq!(reassembler.pending(), 2);
+        assert_eq!(reassembler.buffered, 200);
+        assert!(!reassembler.partials.contains_key(&(src, 0)));
+
+        // a message larger than the cap alone is dropped
+        let mut pieces = fragments(&split(3, &frame, 164));
+        pieces.truncate(3);
+        for piece in pieces {
+            reassembler.insert(src, piece, 164, now + Duration::from_millis(3));
+        }
+        assert!(!reassembler.partials.contains_key(&(src, 3)));
+        assert!(reassembler.buffered <= 250);
+    }
+}
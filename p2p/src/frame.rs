@@ -0,0 +1,45 @@
+//! Innermsg framing, one message per transport frame.
+
+use prost::Message;
+use protos::innermsg::Innermsg;
+use crate::error::{P2pError, P2pResult};
+
+/// Largest udp payload over ipv4, a bigger frame would be truncated or dropped.
+pub const MAX_FRAME: usize = 65507;
+
+pub fn encode(msg: &Innermsg, max_frame: usize) -> P2pResult<Vec<u8>> {
+    let len = msg.encoded_len();
+    if len > max_frame {
+        return Err(P2pError::Oversize(len));
+    }
+    Ok(msg.encode_to_vec())
+}
+
+pub fn decode(frame: &[u8]) -> P2pResult<Innermsg> {
+    Ok(Innermsg::decode(frame)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protos::innermsg::{Action, Identity};
+    use protos::zmessage::ZMessage;
+
+    #[test]
+    fn frame_roundtrip() {
+        let msg = Innermsg {
+            identity: Identity::Server.into(),
+            action: Action::Write.into(),
+            message: Some(ZMessage { data: vec![7; 32], ..Default::default() }),
+            ..Default::default()
+        };
+        let frame = encode(&msg, MAX_FRAME).unwrap();
+        assert_eq!(decode(&frame).unwrap(), msg);
+
+        let oversize = Innermsg {
+            message: Some(ZMessage { data: vec![0; MAX_FRAME], ..Default::default() }),
+            ..Default::default()
+        };
+        assert!(matches!(encode(&oversize, MAX_FRAME), Err(P2pError::Oversize(_))));
+    }
+}
@@ -0,0 +1,23 @@
+//! Node to node transport of the zchronod nodes.
+//!
+//! [`Network`] owns the inner link (zeb relay and clients) on a udp, tcp,
+//! quic or unix socket [`Transport`] and, in direct push mode, the outer udp link other nodes
+//! are reached on. Every frame is one [`Innermsg`](protos::innermsg::Innermsg).
+//! Messages over a datagram frame are split into fragments and reassembled,
+//! and with a [`RetransmitPolicy`] datagram links ack and retransmit them.
+//! The [`AddressBook`] keeps the address of each known node and tells which
+//! silent nodes are due for a reconnect attempt.
+
+pub mod book;
+pub mod error;
+pub mod fragment;
+pub mod frame;
+pub mod network;
+pub mod reliable;
+pub mod transport;
+
+pub use book::{AddressBook, ReconnectPolicy};
+pub use error::{P2pError, P2pResult};
+pub use network::{Link, Network};
+pub use reliable::RetransmitPolicy;
+pub use transport::{QuicTransport, TcpTransport, Transport, UdpTransport, UnixMode, UnixTransport};
@@ -0,0 +1,386 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use protos::innermsg::Innermsg;
+use tokio::sync::RwLock;
+use tracing::*;
+use crate::book::{AddressBook, ReconnectPolicy};
+use crate::error::{P2pError, P2pResult};
+use crate::fragment::{self, Reassembler};
+use crate::frame;
+use crate::reliable::{self, Dedup, Envelope, Outbox, RetransmitPolicy};
+use crate::transport::Transport;
+
+/// Largest message by default, fragmented when over the transport frame size.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+pub const DEFAULT_REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Socket a message travels on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Link {
+    Inner,      // zeb relay and clients
+    Outer,      // other nodes in direct push mode, the inner link when there is no outer one
+}
+
+pub struct Network {
+    inner: Box<dyn Transport>,
+    outer: Option<Box<dyn Transport>>,
+    book: RwLock<AddressBook>,
+    max_message_size: usize,
+    reassembler: Mutex<Reassembler>,
+    next_message_id: AtomicU64,     // of outgoing reliable and fragmented messages
+    retransmit: Option<RetransmitPolicy>,
+    outbox: Mutex<Outbox>,
+    dedup: Mutex<Dedup>,
+}
+
+impl Network {
+    /// Network over the inner transport and, for direct push mode, the outer one.
+    pub fn new(inner: Box<dyn Transport>, outer: Option<Box<dyn Transport>>, policy: ReconnectPolicy) -> Self {
+        Self {
+            inner,
+            outer,
+            book: RwLock::new(AddressBook::new(policy)),
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            reassembler: Mutex::new(Reassembler::new(DEFAULT_MAX_MESSAGE_SIZE, DEFAULT_REASSEMBLY_TIMEOUT)),
+            // ids of a restarted node must not look like duplicates of its earlier ones
+            next_message_id: AtomicU64::new(now_ms() as u64),
+            retransmit: None,
+            outbox: Mutex::new(Outbox::new(RetransmitPolicy::default())),
+            dedup: Mutex::new(Dedup::default()),
+        }
+    }
+
+    /// Limit the size of messages sent and reassembled, and how long the
+    /// fragments of an incomplete message are kept.
+    pub fn with_fragment_limits(mut self, max_message_size: usize, reassembly_timeout: Duration) -> Self {
+        self.max_message_size = max_message_size;
+        self.reassembler = Mutex::new(Reassembler::new(max_message_size, reassembly_timeout));
+        self
+    }
+
+    /// Send over datagram transports with acks and retransmission.
+    pub fn with_retransmit(mut self, policy: RetransmitPolicy) -> Self {
+        self.retransmit = Some(policy);
+        self.outbox = Mutex::new(Outbox::new(policy));
+        self
+    }
+
+    pub fn has_outer(&self) -> bool {
+        self.outer.is_some()
+    }
+
+    pub fn local_addr(&self, link: Link) -> P2pResult<SocketAddr> {
+        self.transport(link).local_addr()
+    }
+
+    /// Receive the next message on either link, reassembled from its
+    /// fragments. Reliable frames are acked and their duplicates dropped.
+    /// Undecodable frames are skipped, transport errors returned.
+    pub async fn recv(&self) -> P2pResult<(Link, Innermsg, SocketAddr)> {
+        loop {
+            let (link, (data, src)) = match self.outer.as_ref() {
+                Some(outer) => tokio::select! {
+                    received = self.inner.recv() => (Link::Inner, received?),
+                    received = outer.recv() => (Link::Outer, received?),
+                },
+                None => (Link::Inner, self.inner.recv().await?),
+            };
+            let data = match fragment::parse(&data) {
+                None => data,
+                Some(Ok(piece)) => match self.reassembler.lock().unwrap().insert(src, piece, self.transport(link).max_frame(), Instant::now()) {
+                    Some(whole) => whole,
+                    None => continue,
+                },
+                Some(Err(err)) => {
+                    info!("Skip bad fragment from {}, err={}", src, err);
+                    continue;
+                }
+            };
+            let data = match reliable::open(data) {
+                Ok(Envelope::Plain(data)) => data,
+                Ok(Envelope::Reliable(envelope)) => {
+                    if let Err(err) = self.transport(link).send(&reliable::ack(envelope.message_id), src).await {
+                        warn!("Ack {} to {} failed, err={}", envelope.message_id, src, err);
+                    }
+                    if !self.dedup.lock().unwrap().first_time(src, envelope.message_id) {
+                        continue;
+                    }
+                    envelope.frame
+                }
+                Ok(Envelope::Ack(ack)) => {
+                    let mut outbox = self.outbox.lock().unwrap();
+                    for message_id in ack.message_ids {
+                        outbox.ack(message_id, src);
+                    }
+                    continue;
+                }
+                Err(err) => {
+                    info!("Skip bad envelope from {}, err={}", src, err);
+                    continue;
+                }
+            };
+            match frame::decode(&data) {
+                Ok(msg) => return Ok((link, msg, src)),
+                Err(err) => info!("Skip frame from {}, only support innermsg type between vlc & p2p modules at now, err={}", src, err),
+            }
+        }
+    }
+
+    pub async fn send(&self, link: Link, msg: &Innermsg, dest: SocketAddr) -> P2pResult<usize> {
+        let frame = frame::encode(msg, self.max_message_size.max(self.transport(link).max_frame()))?;
+        self.send_frame(link, frame, dest).await
+    }
+
+    /// Send to a node of the address book over the outer link.
+    pub async fn send_to_peer(&self, node_id: &str, msg: &Innermsg) -> P2pResult<usize> {
+        let dest = self.book.read().await.lookup(node_id).ok_or_else(|| P2pError::UnknownPeer(node_id.to_owned()))?;
+        self.send(Link::Outer, msg, dest).await
+    }
+
+    /// Send one frame to each destination over the outer link, skipping
+    /// `exclude`. Returns how many sends succeeded.
+    pub async fn broadcast(&self, msg: &Innermsg, dests: &[SocketAddr], exclude: Option<SocketAddr>) -> usize {
+        let frame = match frame::encode(msg, self.max_message_size.max(self.transport(Link::Outer).max_frame())) {
+            Ok(frame) => frame,
+            Err(err) => {
+                error!("Broadcast dropped, err={}", err);
+                return 0;
+            }
+        };
+        let mut sent = 0;
+        for dest in dests.iter().filter(|dest| Some(**dest) != exclude) {
+            match self.send_frame(Link::Outer, frame.clone(), *dest).await {
+                Ok(_) => sent += 1,
+                Err(err) => warn!("Broadcast to {} failed, err={:?}", dest, err),
+            }
+        }
+        sent
+    }
+
+    pub async fn add_peer(&self, node_id: &str, address: SocketAddr) {
+        self.book.write().await.insert(node_id, address, now_ms());
+    }
+
+    /// Mark a node alive after one of its messages was verified.
+    pub async fn peer_seen(&self, node_id: &str) {
+        self.book.write().await.seen(node_id, now_ms());
+    }
+
+    pub async fn remove_peer(&self, node_id: &str) -> Option<SocketAddr> {
+        self.book.write().await.remove(node_id)
+    }
+
+    pub async fn peer_address(&self, node_id: &str) -> Option<SocketAddr> {
+        self.book.read().await.lookup(node_id)
+    }
+
+    pub async fn peer_addresses(&self) -> Vec<SocketAddr> {
+        self.book.read().await.addresses()
+    }
+
+    /// Silent nodes due for a reconnect attempt.
+    pub async fn due_reconnects(&self) -> Vec<(String, SocketAddr)> {
+        self.book.write().await.due_reconnects(now_ms())
+    }
+
+    /// Send again the reliable frames whose ack is overdue, returns how many.
+    pub async fn retransmit(&self) -> usize {
+        let due = self.outbox.lock().unwrap().due(Instant::now());
+        for (link, dest, datagrams) in due.iter() {
+            for datagram in datagrams {
+                if let Err(err) = self.transport(*link).send(datagram, *dest).await {
+                    warn!("Retransmit to {} failed, err={}", dest, err);
+                    break;
+                }
+            }
+        }
+        due.len()
+    }
+
+    /// Number of reliable frames still waiting for their ack.
+    pub fn unacked(&self) -> usize {
+        self.outbox.lock().unwrap().len()
+    }
+
+    /// Send an encoded message, in a reliable envelope when retransmission is
+    /// on and the transport may lose it, in fragments when over its frame size.
+    async fn send_frame(&self, link: Link, frame: Vec<u8>, dest: SocketAddr) -> P2pResult<usize> {
+        let transport = self.transport(link);
+        let reliable_id = match self.retransmit {
+            Some(_) if !transport.is_reliable() => Some(self.next_message_id.fetch_add(1, Ordering::Relaxed)),
+            _ => None,
+        };
+        let frame = match reliable_id {
+            Some(message_id) => reliable::wrap(message_id, frame),
+            None => frame,
+        };
+        let datagrams = if frame.len() <= transport.max_frame() {
+            vec![frame]
+        } else {
+            fragment::split(self.next_message_id.fetch_add(1, Ordering::Relaxed), &frame, transport.max_frame())
+        };
+        let mut sent = 0;
+        for datagram in datagrams.iter() {
+            sent += transport.send(datagram, dest).await?;
+        }
+        if let Some(message_id) = reliable_id {
+            self.outbox.lock().unwrap().insert(message_id, link, dest, datagrams, Instant::now());
+        }
+        Ok(sent)
+    }
+
+    fn transport(&self, link: Link) -> &dyn Transport {
+        match link {
+            Link::Inner => self.inner.as_ref(),
+            Link::Outer => self.outer.as_deref().unwrap_or(self.inner.as_ref()),
+        }
+    }
+}
+
+fn now_ms() -> u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost::Message;
+    use protos::innermsg::{Action, Identity};
+    use protos::zmessage::ZMessage;
+    use crate::transport::{QuicTransport, TcpTransport, UdpTransport};
+
+    async fn udp() -> Box<dyn Transport> {
+        Box::new(UdpTransport::bind("127.0.0.1:0").await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn send_and_broadcast() {
+        let a = Network::new(udp().await, Some(udp().await), ReconnectPolicy::default());
+        let b = Network::new(udp().await, None, ReconnectPolicy::default());
+        let b_addr = b.local_addr(Link::Inner).unwrap();
+        let msg = Innermsg { identity: Identity::Server.into(), action: Action::Write.into(), ..Default::default() };
+
+        a.add_peer("b", b_addr).await;
+        assert_eq!(a.send_to_peer("b", &msg).await.unwrap(), msg.encoded_len());
+        let (link, received, src) = b.recv().await.unwrap();
+        assert_eq!((link, received), (Link::Inner, msg.clone()));
+        assert_eq!(src, a.local_addr(Link::Outer).unwrap());
+        assert!(matches!(a.send_to_peer("c", &msg).await, Err(P2pError::UnknownPeer(_))));
+
+        assert_eq!(a.broadcast(&msg, &a.peer_addresses().await, Some(b_addr)).await, 0);
+        assert_eq!(a.broadcast(&msg, &a.peer_addresses().await, None).await, 1);
+
+        b.send(Link::Outer, &msg, src).await.unwrap();
+        let (link, _, _) = a.recv().await.unwrap();
+        assert_eq!(link, Link::Outer);
+    }
+
+    #[tokio::test]
+    async fn fragmented_datagrams() {
+        let a = Network::new(udp().await, None, ReconnectPolicy::default());
+        let b = Network::new(udp().await, None, ReconnectPolicy::default())
+            .with_fragment_limits(3 * frame::MAX_FRAME, Duration::from_secs(1));
+        let b_addr = b.local_addr(Link::Inner).unwrap();
+        let message = |len| Innermsg {
+            message: Some(ZMessage { data: vec![3; len], ..Default::default() }),
+            ..Default::default()
+        };
+
+        let large = message(2 * frame::MAX_FRAME);
+        assert!(a.send(Link::Inner, &large, b_addr).await.unwrap() > large.encoded_len());
+        assert_eq!(b.recv().await.unwrap().1, large);
+
+        // over the receiver limit, dropped after the fragments arrived
+        a.send(Link::Inner, &message(4 * frame::MAX_FRAME), b_addr).await.unwrap();
+        let small = message(10);
+        a.send(Link::Inner, &small, b_addr).await.unwrap();
+        assert_eq!(b.recv().await.unwrap().1, small);
+
+        let a = a.with_fragment_limits(frame::MAX_FRAME, Duration::from_secs(1));
+        assert!(matches!(a.send(Link::Inner, &large, b_addr).await, Err(P2pError::Oversize(_))));
+    }
+
+    #[tokio::test]
+    async fn acked_retransmission() {
+        let policy = RetransmitPolicy {
+            ack_timeout: Duration::from_millis(20),
+            max_attempts: 3,
+            backoff_maximum: Duration::from_millis(40),
+        };
+        let a = Network::new(udp().await, None, ReconnectPolicy::default()).with_retransmit(policy);
+        // b stays silent at first, like a lost ack
+        let silent = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let msg = Innermsg { identity: Identity::Server.into(), ..Default::default() };
+        a.send(Link::Inner, &msg, silent.local_addr().unwrap()).await.unwrap();
+        assert_eq!(a.unacked(), 1);
+        tokio::time::sleep(Duration::from_millis(25)).await;
+        assert_eq!(a.retransmit().await, 1);
+
+        let mut buf = vec![0; frame::MAX_FRAME];
+        let (n, _) = silent.recv_from(&mut buf).await.unwrap();
+        let (m, _) = silent.recv_from(&mut buf).await.unwrap();
+        assert_eq!(n, m);
+        let Ok(Envelope::Reliable(envelope)) = reliable::open(buf[..n].to_vec()) else {
+            panic!("not a reliable envelope");
+        };
+        silent.send_to(&reliable::ack(envelope.message_id), a.local_addr(Link::Inner).unwrap()).await.unwrap();
+        // the ack is handled while waiting for the next message
+        silent.send_to(&msg.encode_to_vec(), a.local_addr(Link::Inner).unwrap()).await.unwrap();
+        assert_eq!(a.recv().await.unwrap().1, msg);
+        assert_eq!(a.unacked(), 0);
+
+        // a duplicate reaches the receiver once and is acked twice
+        let b = Network::new(udp().await, None, ReconnectPolicy::default());
+        let b_addr = b.local_addr(Link::Inner).unwrap();
+        let sender = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let datagram = reliable::wrap(9, msg.encode_to_vec());
+        sender.send_to(&datagram, b_addr).await.unwrap();
+        sender.send_to(&datagram, b_addr).await.unwrap();
+        let next = Innermsg { identity: Identity::Client.into(), action: Action::Read.into(), ..Default::default() };
+        sender.send_to(&next.encode_to_vec(), b_addr).await.unwrap();
+        assert_eq!(b.recv().await.unwrap().1, msg);
+        assert_eq!(b.recv().await.unwrap().1, next);
+        for _ in 0..2 {
+            let (n, _) = sender.recv_from(&mut buf).await.unwrap();
+            assert!(matches!(reliable::open(buf[..n].to_vec()), Ok(Envelope::Ack(ack)) if ack.message_ids == vec![9]));
+        }
+    }
+
+    #[tokio::test]
+    async fn stream_transports() {
+        // larger than a udp datagram can carry
+        let large = Innermsg {
+            message: Some(ZMessage { data: vec![1; 2 * frame::MAX_FRAME], ..Default::default() }),
+            ..Default::default()
+        };
+        // both quic ends share one certificate
+        let certified = rcgen::generate_simple_self_signed(vec![crate::transport::quic::SERVER_NAME.to_owned()]).unwrap();
+        let dir = std::env::temp_dir();
+        let cert_path = dir.join(format!("p2p-test-{}.crt", std::process::id()));
+        let key_path = dir.join(format!("p2p-test-{}.key", std::process::id()));
+        std::fs::write(&cert_path, certified.cert.pem()).unwrap();
+        std::fs::write(&key_path, certified.key_pair.serialize_pem()).unwrap();
+        let tls = (cert_path.to_str().unwrap(), key_path.to_str().unwrap());
+
+        let pairs: Vec<(Box<dyn Transport>, Box<dyn Transport>)> = vec![
+            (Box::new(TcpTransport::bind("127.0.0.1:0").await.unwrap()), Box::new(TcpTransport::bind("127.0.0.1:0").await.unwrap())),
+            (Box::new(QuicTransport::bind("127.0.0.1:0", tls).await.unwrap()), Box::new(QuicTransport::bind("127.0.0.1:0", tls).await.unwrap())),
+        ];
+        for (server, client) in pairs {
+            let server = Network::new(server, None, ReconnectPolicy::default());
+            let client = Network::new(client, None, ReconnectPolicy::default());
+            let server_addr = server.local_addr(Link::Inner).unwrap();
+
+            client.send(Link::Inner, &large, server_addr).await.unwrap();
+            let (_, received, src) = server.recv().await.unwrap();
+            assert_eq!(received, large);
+            // the reply reuses the connection the client opened
+            server.send(Link::Inner, &large, src).await.unwrap();
+            assert_eq!(client.recv().await.unwrap().1, large);
+        }
+        std::fs::remove_file(cert_path).unwrap_or(());
+        std::fs::remove_file(key_path).unwrap_or(());
+    }
+}
@@ -0,0 +1,244 @@
+//! Acknowledged delivery over datagram transports.
+//!
+//! A reliable frame travels as a [`Reliable`] envelope after [`RELIABLE_MARK`].
+//! The receiver answers each one with an [`Ack`], also for duplicates whose
+//! first ack may have been lost, and hands a message id from a sender on only
+//! once. The sender keeps the frame in its [`Outbox`] and sends it again with
+//! doubling delay until acked or out of attempts.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use prost::Message;
+use protos::innermsg::{Ack, Reliable};
+use tracing::*;
+use crate::network::Link;
+
+/// First byte of a reliable envelope, like the fragment mark no Innermsg starts with it.
+pub const RELIABLE_MARK: u8 = 1;
+/// First byte of an acknowledgement.
+pub const ACK_MARK: u8 = 2;
+
+/// Message ids remembered per sender for duplicate suppression.
+const DEDUP_WINDOW: usize = 4096;
+
+/// Senders remembered for duplicate suppression, the least recently seen one
+/// is forgotten to make room.
+const DEDUP_SENDERS: usize = 1024;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetransmitPolicy {
+    pub ack_timeout: Duration,      // before the first retransmission
+    pub max_attempts: u32,          // sends of one frame, the first included
+    pub backoff_maximum: Duration,  // cap of the doubled delay
+}
+
+impl Default for RetransmitPolicy {
+    fn default() -> Self {
+        Self {
+            ack_timeout: Duration::from_millis(500),
+            max_attempts: 5,
+            backoff_maximum: Duration::from_secs(8),
+        }
+    }
+}
+
+pub enum Envelope {
+    Plain(Vec<u8>),
+    Reliable(Reliable),
+    Ack(Ack),
+}
+
+pub fn wrap(message_id: u64, frame: Vec<u8>) -> Vec<u8> {
+    with_mark(RELIABLE_MARK, &Reliable { message_id, frame })
+}
+
+pub fn ack(message_id: u64) -> Vec<u8> {
+    with_mark(ACK_MARK, &Ack { message_ids: vec![message_id] })
+}
+
+pub fn open(datagram: Vec<u8>) -> Result<Envelope, prost::DecodeError> {
+    Ok(match datagram.first() {
+        Some(&RELIABLE_MARK) => Envelope::Reliable(Reliable::decode(&datagram[1..])?),
+        Some(&ACK_MARK) => Envelope::Ack(Ack::decode(&datagram[1..])?),
+        _ => Envelope::Plain(datagram),
+    })
+}
+
+fn with_mark(mark: u8, msg: &impl Message) -> Vec<u8> {
+    let mut datagram = Vec::with_capacity(1 + msg.encoded_len());
+    datagram.push(mark);
+    msg.encode(&mut datagram).expect("vec grows as needed");
+    datagram
+}
+
+struct Outstanding {
+    link: Link,
+    dest: SocketAddr,
+    datagrams: Vec<Vec<u8>>,
+    attempts: u32,
+    delay: Duration,
+    next_at: Instant,
+}
+
+/// Sent reliable frames waiting for their ack.
+pub struct Outbox {
+    policy: RetransmitPolicy,
+    outstanding: HashMap<u64, Outstanding>,
+}
+
+impl Outbox {
+    pub fn new(policy: RetransmitPolicy) -> Self {
+        Self { policy, outstanding: HashMap::new() }
+    }
+
+    /// Record the datagrams of a frame just sent for the first time.
+    pub fn insert(&mut self, message_id: u64, link: Link, dest: SocketAddr, datagrams: Vec<Vec<u8>>, now: Instant) {
+        let delay = self.policy.ack_timeout;
+        self.outstanding.insert(message_id, Outstanding { link, dest, datagrams, attempts: 1, delay, next_at: now + delay });
+    }
+
+    /// Return false for an unknown or already acked id, or an ack from
+    /// another address than the frame was sent to.
+    pub fn ack(&mut self, message_id: u64, src: SocketAddr) -> bool {
+        match self.outstanding.get(&message_id) {
+            Some(entry) if entry.dest == src => self.outstanding.remove(&message_id).is_some(),
+            Some(entry) => {
+                warn!("Ignore ack of message {} from {}, it was sent to {}", message_id, src, entry.dest);
+                false
+            }
+            None => false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.outstanding.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.outstanding.is_empty()
+    }
+
+    /// Frames to send again now, the ones out of attempts are given up.
+    pub fn due(&mut self, now: Instant) -> Vec<(Link, SocketAddr, Vec<Vec<u8>>)> {
+        let policy = self.policy;
+        let mut due = Vec::new();
+        self.outstanding.retain(|message_id, entry| {
+            if entry.next_at > now {
+                return true;
+            }
+            if entry.attempts >= policy.max_attempts {
+                warn!("Give up message {} to {} after {} attempts", message_id, entry.dest, entry.attempts);
+                return false;
+            }
+            entry.attempts += 1;
+            entry.delay = (entry.delay * 2).min(policy.backoff_maximum);
+            entry.next_at = now + entry.delay;
+            due.push((entry.link, entry.dest, entry.datagrams.clone()));
+            true
+        });
+        due
+    }
+}
+
+#[derive(Default)]
+struct SenderIds {
+    ids: HashSet<u64>,
+    order: VecDeque<u64>,
+    last_seen: u64,     // arrival number of its latest message
+}
+
+/// Recently delivered message ids of the recent senders.
+#[derive(Default)]
+pub struct Dedup {
+    seen: HashMap<SocketAddr, SenderIds>,
+    arrivals: u64,
+}
+
+impl Dedup {
+    /// Return true the first time `message_id` arrives from `src`.
+    pub fn first_time(&mut self, src: SocketAddr, message_id: u64) -> bool {
+        if !self.seen.contains_key(&src) && self.seen.len() >= DEDUP_SENDERS {
+            self.forget_least_recent();
+        }
+        self.arrivals += 1;
+        let sender = self.seen.entry(src).or_default();
+        sender.last_seen = self.arrivals;
+        if !sender.ids.insert(message_id) {
+            return false;
+        }
+        sender.order.push_back(message_id);
+        if sender.order.len() > DEDUP_WINDOW {
+            if let Some(oldest) = sender.order.pop_front() {
+                sender.ids.remove(&oldest);
+            }
+        }
+        true
+    }
+
+    fn forget_least_recent(&mut self) {
+        let least_recent = self.seen.iter().min_by_key(|(_, sender)| sender.last_seen).map(|(src, _)| *src);
+        if let Some(src) = least_recent {
+            self.seen.remove(&src);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retransmit_until_acked() {
+        let policy = RetransmitPolicy {
+            ack_timeout: Duration::from_millis(10),
+            max_attempts: 3,
+            backoff_maximum: Duration::from_millis(15),
+        };
+        let dest: SocketAddr = "127.0.0.1:8050".parse().unwrap();
+        let now = Instant::now();
+        let mut outbox = Outbox::new(policy);
+        outbox.insert(1, Link::Inner, dest, vec![wrap(1, vec![8])], now);
+        outbox.insert(2, Link::Outer, dest, vec![wrap(2, vec![9])], now);
+
+        assert!(outbox.due(now + Duration::from_millis(9)).is_empty());
+        assert_eq!(outbox.due(now + Duration::from_millis(10)).len(), 2);
+        // only the destination can ack
+        assert!(!outbox.ack(2, "127.0.0.1:8051".parse().unwrap()));
+        assert!(outbox.ack(2, dest));
+        assert!(!outbox.ack(2, dest));
+        // delay doubled to 20, capped at 15
+        assert!(outbox.due(now + Duration::from_millis(24)).is_empty());
+        assert_eq!(outbox.due(now + Duration::from_millis(25)).len(), 1);
+        // third attempt was the last one
+        assert!(outbox.due(now + Duration::from_millis(40)).is_empty());
+        assert!(outbox.is_empty());
+
+        let mut dedup = Dedup::default();
+        assert!(dedup.first_time(dest, 1));
+        assert!(!dedup.first_time(dest, 1));
+        assert!(dedup.first_time("127.0.0.1:8051".parse().unwrap(), 1));
+
+        match open(wrap(7, vec![1, 2])).unwrap() {
+            Envelope::Reliable(reliable) => assert_eq!((reliable.message_id, reliable.frame), (7, vec![1, 2])),
+            _ => panic!("not a reliable envelope"),
+        }
+        assert!(matches!(open(ack(7)).unwrap(), Envelope::Ack(ack) if ack.message_ids == vec![7]));
+        assert!(matches!(open(vec![0x08, 0x01]).unwrap(), Envelope::Plain(_)));
+    }
+
+    #[test]
+    fn dedup_caps_senders() {
+        let sender = |port: u16| SocketAddr::from(([127, 0, 0, 2], port));
+        let mut dedup = Dedup::default();
+        for port in 0..DEDUP_SENDERS as u16 {
+            assert!(dedup.first_time(sender(port), 1));
+        }
+        // the first sender is seen again, the second one is now the least recent
+        assert!(!dedup.first_time(sender(0), 1));
+        assert!(dedup.first_time(sender(DEDUP_SENDERS as u16), 1));
+        assert_eq!(dedup.seen.len(), DEDUP_SENDERS);
+        assert!(!dedup.first_time(sender(0), 1));
+        assert!(!dedup.seen.contains_key(&sender(1)));
+    }
+}
@@ -0,0 +1,60 @@
+//! Byte transports a [`Network`](crate::Network) link runs on.
+//!
+//! A transport moves whole frames: a udp or unix datagram, a length-prefixed
+//! tcp or unix stream record, or a quic unidirectional stream. The `Innermsg`
+//! encoding on top is the same for all of them.
+
+pub mod quic;
+pub mod tcp;
+pub mod udp;
+pub mod unix;
+
+use std::net::SocketAddr;
+use async_trait::async_trait;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use crate::error::P2pResult;
+
+pub use quic::QuicTransport;
+pub use tcp::TcpTransport;
+pub use udp::UdpTransport;
+pub use unix::{UnixMode, UnixTransport};
+
+/// Largest frame of the stream transports.
+pub const MAX_STREAM_FRAME: usize = 16 * 1024 * 1024;
+
+#[async_trait]
+pub trait Transport: Send + Sync {
+    /// Send one frame to `dest`, connecting first when needed.
+    async fn send(&self, frame: &[u8], dest: SocketAddr) -> P2pResult<usize>;
+
+    /// Next frame received from any remote, cancel safe.
+    async fn recv(&self) -> P2pResult<(Vec<u8>, SocketAddr)>;
+
+    fn local_addr(&self) -> P2pResult<SocketAddr>;
+
+    /// Largest frame `send` accepts.
+    fn max_frame(&self) -> usize;
+
+    /// Whether frames arrive without loss or duplication, so need no acks.
+    fn is_reliable(&self) -> bool {
+        false
+    }
+}
+
+/// Read one big-endian u32 length-prefixed frame, None at the end of the
+/// stream or on a frame over [`MAX_STREAM_FRAME`].
+pub(crate) async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Option<Vec<u8>> {
+    let len = reader.read_u32().await.ok()? as usize;
+    if len > MAX_STREAM_FRAME {
+        tracing::warn!("Drop stream, frame of {} bytes is too large", len);
+        return None;
+    }
+    let mut frame = vec![0; len];
+    reader.read_exact(&mut frame).await.ok()?;
+    Some(frame)
+}
+
+pub(crate) async fn write_frame<W: AsyncWrite + Unpin>(writer: &mut W, frame: &[u8]) -> std::io::Result<()> {
+    writer.write_u32(frame.len() as u32).await?;
+    writer.write_all(frame).await
+}
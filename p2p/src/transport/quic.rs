@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use quinn::rustls::pki_types::pem::PemObject;
+use quinn::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use quinn::rustls::RootCertStore;
+use quinn::{ClientConfig, Connection, Endpoint, ServerConfig, TransportConfig};
+use tokio::sync::{mpsc, Mutex};
+use tracing::*;
+use crate::error::{P2pError, P2pResult};
+use super::{Transport, MAX_STREAM_FRAME};
+
+/// Server name the link certificate is issued for and dialed with.
+pub const SERVER_NAME: &str = "zchronod";
+
+const KEEP_ALIVE: Duration = Duration::from_secs(10);
+
+type Connections = Arc<Mutex<HashMap<SocketAddr, Connection>>>;
+
+/// One unidirectional quic stream per frame. Outgoing connections trust the
+/// transport's own certificate chain, so both ends of the link must be
+/// configured with the same one.
+pub struct QuicTransport {
+    endpoint: Endpoint,
+    connections: Connections,
+    frames_tx: mpsc::Sender<(Vec<u8>, SocketAddr)>,
+    frames_rx: Mutex<mpsc::Receiver<(Vec<u8>, SocketAddr)>>,
+}
+
+impl QuicTransport {
+    /// Bind with the PEM certificate chain and key at `tls`, issued for [`SERVER_NAME`].
+    pub async fn bind(address: &str, tls: (&str, &str)) -> P2pResult<Self> {
+        let address: SocketAddr = address.parse().map_err(|err| P2pError::QuicError(format!("bad address {}: {}", address, err)))?;
+        let (certs, key) = load_pem(tls.0, tls.1)?;
+        let mut transport_config = TransportConfig::default();
+        transport_config.keep_alive_interval(Some(KEEP_ALIVE));
+        let transport_config = Arc::new(transport_config);
+
+        let mut roots = RootCertStore::empty();
+        for cert in certs.iter() {
+            roots.add(cert.clone()).map_err(quic_error)?;
+        }
+        let mut client_config = ClientConfig::with_root_certificates(Arc::new(roots)).map_err(quic_error)?;
+        client_config.transport_config(transport_config.clone());
+        let mut server_config = ServerConfig::with_single_cert(certs, key).map_err(quic_error)?;
+        server_config.transport_config(transport_config);
+
+        let mut endpoint = Endpoint::server(server_config, address)?;
+        endpoint.set_default_client_config(client_config);
+        let (frames_tx, frames_rx) = mpsc::channel(1024);
+        let connections = Connections::default();
+        tokio::spawn(accept_loop(endpoint.clone(), connections.clone(), frames_tx.clone()));
+        Ok(Self { endpoint, connections, frames_tx, frames_rx: Mutex::new(frames_rx) })
+    }
+
+    /// Open connection to `dest`, dialed without holding the connections
+    /// lock so a slow peer doesn't stall sends to the others.
+    async fn connection(&self, dest: SocketAddr) -> P2pResult<Connection> {
+        if let Some(connection) = open_connection(&self.connections, dest).await {
+            return Ok(connection);
+        }
+        let connection = self.endpoint.connect(dest, SERVER_NAME).map_err(quic_error)?.await.map_err(quic_error)?;
+        let mut connections = self.connections.lock().await;
+        // a concurrent send or the peer may have connected meanwhile, keep that one
+        if let Some(current) = connections.get(&dest).filter(|current| current.close_reason().is_none()) {
+            return Ok(current.clone());
+        }
+        connections.insert(dest, connection.clone());
+        tokio::spawn(read_loop(connection.clone(), self.connections.clone(), self.frames_tx.clone()));
+        Ok(connection)
+    }
+}
+
+#[async_trait]
+impl Transport for QuicTransport {
+    async fn send(&self, frame: &[u8], dest: SocketAddr) -> P2pResult<usize> {
+        if frame.len() > MAX_STREAM_FRAME {
+            return Err(P2pError::Oversize(frame.len()));
+        }
+        let connection = self.connection(dest).await?;
+        let mut stream = connection.open_uni().await.map_err(quic_error)?;
+        stream.write_all(frame).await.map_err(quic_error)?;
+        stream.finish().map_err(quic_error)?;
+        Ok(frame.len())
+    }
+
+    async fn recv(&self) -> P2pResult<(Vec<u8>, SocketAddr)> {
+        self.frames_rx.lock().await.recv().await
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::BrokenPipe).into())
+    }
+
+    fn local_addr(&self) -> P2pResult<SocketAddr> {
+        Ok(self.endpoint.local_addr()?)
+    }
+
+    fn max_frame(&self) -> usize {
+        MAX_STREAM_FRAME
+    }
+
+    fn is_reliable(&self) -> bool {
+        true
+    }
+}
+
+async fn open_connection(connections: &Connections, dest: SocketAddr) -> Option<Connection> {
+    let connections = connections.lock().await;
+    connections.get(&dest).filter(|connection| connection.close_reason().is_none()).cloned()
+}
+
+async fn accept_loop(endpoint: Endpoint, connections: Connections, frames_tx: mpsc::Sender<(Vec<u8>, SocketAddr)>) {
+    while let Some(incoming) = endpoint.accept().await {
+        match incoming.await {
+            Ok(connection) => {
+                connections.lock().await.insert(connection.remote_address(), connection.clone());
+                tokio::spawn(read_loop(connection, connections.clone(), frames_tx.clone()));
+            }
+            Err(err) => warn!("Quic handshake failed, err={}", err),
+        }
+    }
+}
+
+async fn read_loop(connection: Connection, connections: Connections, frames_tx: mpsc::Sender<(Vec<u8>, SocketAddr)>) {
+    let remote = connection.remote_address();
+    while let Ok(mut stream) = connection.accept_uni().await {
+        let frames_tx = frames_tx.clone();
+        tokio::spawn(async move {
+            match stream.read_to_end(MAX_STREAM_FRAME).await {
+                Ok(frame) => frames_tx.send((frame, remote)).await.unwrap_or(()),
+                Err(err) => warn!("Quic stream of {} dropped, err={}", remote, err),
+            }
+        });
+    }
+    info!("Quic connection of {} closed", remote);
+    let mut connections = connections.lock().await;
+    if connections.get(&remote).is_some_and(|current| current.stable_id() == connection.stable_id()) {
+        connections.remove(&remote);
+    }
+}
+
+fn load_pem(cert_path: &str, key_path: &str) -> P2pResult<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| P2pError::QuicError(format!("bad certificate {}: {}", cert_path, err)))?;
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .map_err(|err| P2pError::QuicError(format!("bad key {}: {}", key_path, err)))?;
+    Ok((certs, key))
+}
+
+fn quic_error(err: impl std::fmt::Display) -> P2pError {
+    P2pError::QuicError(err.to_string())
+}
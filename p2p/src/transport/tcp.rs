@@ -0,0 +1,122 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use async_trait::async_trait;
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Mutex};
+use tracing::*;
+use crate::error::{P2pError, P2pResult};
+use super::{read_frame, write_frame, Transport, MAX_STREAM_FRAME};
+
+type Writer = Arc<Mutex<OwnedWriteHalf>>;
+type Connections = Arc<Mutex<HashMap<SocketAddr, Writer>>>;
+
+/// Frames as big-endian u32 length plus payload over one tcp connection per
+/// remote, accepted or dialed on the first send. Dialing and writing don't
+/// hold the connections lock, so a slow remote only delays its own frames.
+pub struct TcpTransport {
+    local: SocketAddr,
+    connections: Connections,
+    frames_tx: mpsc::Sender<(Vec<u8>, SocketAddr)>,
+    frames_rx: Mutex<mpsc::Receiver<(Vec<u8>, SocketAddr)>>,
+}
+
+impl TcpTransport {
+    pub async fn bind(address: &str) -> P2pResult<Self> {
+        let listener = TcpListener::bind(address).await?;
+        let local = listener.local_addr()?;
+        let (frames_tx, frames_rx) = mpsc::channel(1024);
+        let connections = Connections::default();
+        tokio::spawn(accept_loop(listener, connections.clone(), frames_tx.clone()));
+        Ok(Self { local, connections, frames_tx, frames_rx: Mutex::new(frames_rx) })
+    }
+
+    async fn writer(&self, dest: SocketAddr) -> Option<Writer> {
+        self.connections.lock().await.get(&dest).cloned()
+    }
+
+    /// Dial `dest` without holding the connections lock.
+    async fn connect(&self, dest: SocketAddr) -> P2pResult<Writer> {
+        let (reader, writer) = TcpStream::connect(dest).await?.into_split();
+        let mut connections = self.connections.lock().await;
+        Ok(match connections.entry(dest) {
+            // a concurrent send or the remote connected meanwhile, ours is dropped
+            Entry::Occupied(entry) => entry.get().clone(),
+            Entry::Vacant(entry) => {
+                let writer = entry.insert(Arc::new(Mutex::new(writer))).clone();
+                tokio::spawn(read_loop(reader, dest, writer.clone(), self.connections.clone(), self.frames_tx.clone()));
+                writer
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl Transport for TcpTransport {
+    async fn send(&self, frame: &[u8], dest: SocketAddr) -> P2pResult<usize> {
+        if frame.len() > MAX_STREAM_FRAME {
+            return Err(P2pError::Oversize(frame.len()));
+        }
+        let writer = match self.writer(dest).await {
+            Some(writer) => writer,
+            None => self.connect(dest).await?,
+        };
+        let written = write_frame(&mut *writer.lock().await, frame).await;
+        if let Err(err) = written {
+            remove_writer(&self.connections, dest, &writer).await;
+            return Err(err.into());
+        }
+        Ok(frame.len())
+    }
+
+    async fn recv(&self) -> P2pResult<(Vec<u8>, SocketAddr)> {
+        self.frames_rx.lock().await.recv().await
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::BrokenPipe).into())
+    }
+
+    fn local_addr(&self) -> P2pResult<SocketAddr> {
+        Ok(self.local)
+    }
+
+    fn max_frame(&self) -> usize {
+        MAX_STREAM_FRAME
+    }
+
+    fn is_reliable(&self) -> bool {
+        true
+    }
+}
+
+async fn accept_loop(listener: TcpListener, connections: Connections, frames_tx: mpsc::Sender<(Vec<u8>, SocketAddr)>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, remote)) => {
+                let (reader, writer) = stream.into_split();
+                let writer = Arc::new(Mutex::new(writer));
+                connections.lock().await.insert(remote, writer.clone());
+                tokio::spawn(read_loop(reader, remote, writer, connections.clone(), frames_tx.clone()));
+            }
+            Err(err) => warn!("Tcp accept failed, err={:?}", err),
+        }
+    }
+}
+
+async fn read_loop(mut reader: OwnedReadHalf, remote: SocketAddr, writer: Writer, connections: Connections, frames_tx: mpsc::Sender<(Vec<u8>, SocketAddr)>) {
+    while let Some(frame) = read_frame(&mut reader).await {
+        if frames_tx.send((frame, remote)).await.is_err() {
+            break;
+        }
+    }
+    info!("Tcp connection of {} closed", remote);
+    remove_writer(&connections, remote, &writer).await;
+}
+
+/// Forget the connection of `writer`, unless `remote` already has a newer one.
+async fn remove_writer(connections: &Connections, remote: SocketAddr, writer: &Writer) {
+    let mut connections = connections.lock().await;
+    if connections.get(&remote).is_some_and(|current| Arc::ptr_eq(current, writer)) {
+        connections.remove(&remote);
+    }
+}
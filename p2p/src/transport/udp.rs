@@ -0,0 +1,40 @@
+use std::net::SocketAddr;
+use async_trait::async_trait;
+use tokio::net::UdpSocket;
+use crate::error::P2pResult;
+use crate::frame::MAX_FRAME;
+use super::Transport;
+
+/// One frame per datagram, nothing is retransmitted.
+pub struct UdpTransport {
+    socket: UdpSocket,
+}
+
+impl UdpTransport {
+    pub async fn bind(address: &str) -> P2pResult<Self> {
+        Ok(Self { socket: UdpSocket::bind(address).await? })
+    }
+}
+
+#[async_trait]
+impl Transport for UdpTransport {
+    async fn send(&self, frame: &[u8], dest: SocketAddr) -> P2pResult<usize> {
+        Ok(self.socket.send_to(frame, dest).await?)
+    }
+
+    async fn recv(&self) -> P2pResult<(Vec<u8>, SocketAddr)> {
+        // one byte more than a frame, so an oversize datagram can't pass as a whole one
+        let mut buf = vec![0; MAX_FRAME + 1];
+        let (n, src) = self.socket.recv_from(&mut buf).await?;
+        buf.truncate(n);
+        Ok((buf, src))
+    }
+
+    fn local_addr(&self) -> P2pResult<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    fn max_frame(&self) -> usize {
+        MAX_FRAME
+    }
+}
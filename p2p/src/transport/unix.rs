@@ -0,0 +1,238 @@
+//! Unix domain socket transport for a relay on the same host.
+//!
+//! Access is controlled by the permission bits of the socket file. The rest of
+//! the node keys remotes by `SocketAddr`, so each unix remote gets a stand-in
+//! address in the discard-only prefix `100::/64` that never reaches a network.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{Ipv6Addr, SocketAddr};
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use async_trait::async_trait;
+use tokio::net::unix::OwnedWriteHalf;
+use tokio::net::{UnixDatagram, UnixListener};
+use tokio::sync::{mpsc, Mutex};
+use tracing::*;
+use crate::error::{P2pError, P2pResult};
+use crate::frame::MAX_FRAME;
+use super::{read_frame, write_frame, Transport, MAX_STREAM_FRAME};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnixMode {
+    Datagram,   // one frame per datagram, remotes must bind a path to get replies
+    Stream,     // length-prefixed frames, one connection per remote
+}
+
+type Writers = Arc<Mutex<HashMap<SocketAddr, OwnedWriteHalf>>>;
+
+enum Socket {
+    Datagram(UnixDatagram),
+    Stream {
+        writers: Writers,
+        frames_rx: Mutex<mpsc::Receiver<(Vec<u8>, SocketAddr)>>,
+    },
+}
+
+pub struct UnixTransport {
+    path: PathBuf,
+    socket: Socket,
+    remotes: Arc<Remotes>,
+}
+
+impl UnixTransport {
+    /// Bind the socket file at `path` with permission bits `mode`, replacing a
+    /// stale socket file left by a previous run.
+    pub async fn bind(path: &str, mode: UnixMode, permissions: u32) -> P2pResult<Self> {
+        let path = PathBuf::from(path);
+        if std::fs::symlink_metadata(&path).is_ok_and(|meta| meta.file_type().is_socket()) {
+            std::fs::remove_file(&path)?;
+        }
+        let remotes = Arc::new(Remotes::default());
+        let socket = match mode {
+            UnixMode::Datagram => Socket::Datagram(with_mode(permissions, || UnixDatagram::bind(&path))?),
+            UnixMode::Stream => {
+                let listener = with_mode(permissions, || UnixListener::bind(&path))?;
+                let (frames_tx, frames_rx) = mpsc::channel(1024);
+                let writers = Writers::default();
+                tokio::spawn(accept_loop(listener, writers.clone(), remotes.clone(), frames_tx));
+                Socket::Stream { writers, frames_rx: Mutex::new(frames_rx) }
+            }
+        };
+        Ok(Self { path, socket, remotes })
+    }
+}
+
+impl Drop for UnixTransport {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.path).unwrap_or(());
+    }
+}
+
+#[async_trait]
+impl Transport for UnixTransport {
+    async fn send(&self, frame: &[u8], dest: SocketAddr) -> P2pResult<usize> {
+        if frame.len() > self.max_frame() {
+            return Err(P2pError::Oversize(frame.len()));
+        }
+        match &self.socket {
+            Socket::Datagram(socket) => {
+                let path = self.remotes.path(dest).await.ok_or_else(|| not_connected(dest))?;
+                Ok(socket.send_to(frame, path).await?)
+            }
+            Socket::Stream { writers, .. } => {
+                let mut writers = writers.lock().await;
+                let writer = writers.get_mut(&dest).ok_or_else(|| not_connected(dest))?;
+                if let Err(err) = write_frame(writer, frame).await {
+                    writers.remove(&dest);
+                    return Err(err.into());
+                }
+                Ok(frame.len())
+            }
+        }
+    }
+
+    async fn recv(&self) -> P2pResult<(Vec<u8>, SocketAddr)> {
+        match &self.socket {
+            Socket::Datagram(socket) => {
+                let mut buf = vec![0; MAX_FRAME + 1];
+                let (n, src) = socket.recv_from(&mut buf).await?;
+                buf.truncate(n);
+                let stand_in = match src.as_pathname() {
+                    Some(path) => self.remotes.stand_in(path).await,
+                    // an unbound remote can't be answered
+                    None => self.remotes.next(),
+                };
+                Ok((buf, stand_in))
+            }
+            Socket::Stream { frames_rx, .. } => frames_rx.lock().await.recv().await
+                .ok_or_else(|| io::Error::from(io::ErrorKind::BrokenPipe).into()),
+        }
+    }
+
+    fn local_addr(&self) -> P2pResult<SocketAddr> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, format!("unix socket {} has no socket address", self.path.display())).into())
+    }
+
+    fn max_frame(&self) -> usize {
+        match self.socket {
+            Socket::Datagram(_) => MAX_FRAME,
+            Socket::Stream { .. } => MAX_STREAM_FRAME,
+        }
+    }
+
+    fn is_reliable(&self) -> bool {
+        matches!(self.socket, Socket::Stream { .. })
+    }
+}
+
+/// Stand-in addresses of the unix remotes.
+#[derive(Default)]
+struct Remotes {
+    counter: AtomicU64,
+    paths: Mutex<HashMap<SocketAddr, PathBuf>>,
+}
+
+impl Remotes {
+    fn next(&self) -> SocketAddr {
+        let n = self.counter.fetch_add(1, Ordering::Relaxed) + 1;
+        SocketAddr::new(Ipv6Addr::from((0x100u128 << 112) | u128::from(n)).into(), 0)
+    }
+
+    /// Stand-in of a bound datagram remote, the same one for each of its datagrams.
+    async fn stand_in(&self, path: &Path) -> SocketAddr {
+        let mut paths = self.paths.lock().await;
+        if let Some((addr, _)) = paths.iter().find(|(_, known)| *known == path) {
+            return *addr;
+        }
+        let addr = self.next();
+        paths.insert(addr, path.to_path_buf());
+        addr
+    }
+
+    async fn path(&self, addr: SocketAddr) -> Option<PathBuf> {
+        self.paths.lock().await.get(&addr).cloned()
+    }
+}
+
+async fn accept_loop(listener: UnixListener, writers: Writers, remotes: Arc<Remotes>, frames_tx: mpsc::Sender<(Vec<u8>, SocketAddr)>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let remote = remotes.next();
+                let (mut reader, writer) = stream.into_split();
+                writers.lock().await.insert(remote, writer);
+                let writers = writers.clone();
+                let frames_tx = frames_tx.clone();
+                tokio::spawn(async move {
+                    while let Some(frame) = read_frame(&mut reader).await {
+                        if frames_tx.send((frame, remote)).await.is_err() {
+                            break;
+                        }
+                    }
+                    info!("Unix connection {} closed", remote);
+                    writers.lock().await.remove(&remote);
+                });
+            }
+            Err(err) => warn!("Unix accept failed, err={:?}", err),
+        }
+    }
+}
+
+/// Run `bind` under a umask that leaves only `mode`, so the socket file has
+/// its permission bits from creation on and is never open to anyone else.
+fn with_mode<T>(mode: u32, bind: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+    // SAFETY: umask only swaps the process file mode mask and can't fail
+    let previous = unsafe { libc::umask(!mode as libc::mode_t & 0o777) };
+    let bound = bind();
+    // SAFETY: as above
+    unsafe { libc::umask(previous) };
+    bound
+}
+
+fn not_connected(dest: SocketAddr) -> P2pError {
+    io::Error::new(io::ErrorKind::NotConnected, format!("no unix remote behind {}", dest)).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tokio::net::UnixStream;
+
+    #[tokio::test]
+    async fn unix_transports() {
+        let dir = std::env::temp_dir().join(format!("p2p-unix-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let server_path = dir.join("server.sock");
+        let server_path = server_path.to_str().unwrap();
+
+        let server = UnixTransport::bind(server_path, UnixMode::Datagram, 0o600).await.unwrap();
+        assert_eq!(std::fs::metadata(server_path).unwrap().permissions().mode() & 0o777, 0o600);
+        let client_path = dir.join("client.sock");
+        let client = UnixDatagram::bind(&client_path).unwrap();
+        client.send_to(b"ping", server_path).await.unwrap();
+        let (frame, src) = server.recv().await.unwrap();
+        assert_eq!(frame, b"ping");
+        server.send(b"pong", src).await.unwrap();
+        let mut buf = [0; 4];
+        client.recv(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"pong");
+        drop(server);
+        assert!(!Path::new(server_path).exists());
+
+        let server = UnixTransport::bind(server_path, UnixMode::Stream, 0o660).await.unwrap();
+        let mut client = UnixStream::connect(server_path).await.unwrap();
+        write_frame(&mut client, b"ping").await.unwrap();
+        let (frame, src) = server.recv().await.unwrap();
+        assert_eq!(frame, b"ping");
+        server.send(b"pong", src).await.unwrap();
+        assert_eq!(read_frame(&mut client).await.unwrap(), b"pong");
+        assert!(server.send(b"pong", "127.0.0.1:1".parse().unwrap()).await.is_err());
+
+        drop(server);
+        std::fs::remove_dir_all(dir).unwrap_or(());
+    }
+}
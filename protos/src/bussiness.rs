@@ -36,11 +36,33 @@ pub struct ZGateway {
     pub data: ::prost::alloc::vec::Vec<u8>,
 }
 /// ZGateway.type = GATEWAY_TYPE_NODE_INFO
+/// QUERY_BY_MSGID takes the node id as msg_id, QUERY_BY_TABLE_KEYID pages all known peers
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct NodeInfo {
     #[prost(string, repeated, tag = "1")]
     pub node_ids: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    #[prost(message, repeated, tag = "2")]
+    pub peers: ::prost::alloc::vec::Vec<PeerInfo>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PeerInfo {
+    #[prost(string, tag = "1")]
+    pub node_id: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub address: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub public_key: ::prost::alloc::string::String,
+    /// milliseconds, 0 before the first heartbeat
+    #[prost(uint64, tag = "4")]
+    pub last_heartbeat: u64,
+    /// last verified clock seen from the node
+    #[prost(message, optional, tag = "5")]
+    pub last_clock: ::core::option::Option<super::vlc::ClockInfo>,
+    /// heartbeat within the heartbeat timeout
+    #[prost(bool, tag = "6")]
+    pub alive: bool,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -79,6 +101,17 @@ pub struct QueryStatus {
     #[prost(uint64, tag = "3")]
     pub zmessage_total: u64,
 }
+/// ZGateway.method = QUERY_STABLE_FRONTIER
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct StableFrontier {
+    /// pointwise minimum of the known node clocks
+    #[prost(message, optional, tag = "1")]
+    pub clock: ::core::option::Option<super::vlc::Clock>,
+    /// peers the frontier is taken over, besides this node
+    #[prost(string, repeated, tag = "2")]
+    pub node_ids: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
 #[repr(i32)]
 pub enum GatewayType {
@@ -120,6 +153,12 @@ pub enum QueryMethod {
     QueryByMsgid = 0,
     QueryByTableKeyid = 1,
     QueryStatus = 2,
+    /// event triggers waiting in the causal buffer, replied as ClockInfos
+    QueryPending = 3,
+    /// ZMessages in the deterministic total order, params QueryByTableKeyID as offset
+    QueryTotalOrder = 4,
+    /// events every known node has seen, replied as StableFrontier
+    QueryStableFrontier = 5,
 }
 impl QueryMethod {
     /// String value of the enum field names used in the ProtoBuf definition.
@@ -131,6 +170,9 @@ impl QueryMethod {
             QueryMethod::QueryByMsgid => "QUERY_BY_MSGID",
             QueryMethod::QueryByTableKeyid => "QUERY_BY_TABLE_KEYID",
             QueryMethod::QueryStatus => "QUERY_STATUS",
+            QueryMethod::QueryPending => "QUERY_PENDING",
+            QueryMethod::QueryTotalOrder => "QUERY_TOTAL_ORDER",
+            QueryMethod::QueryStableFrontier => "QUERY_STABLE_FRONTIER",
         }
     }
     /// Creates an enum from field names used in the ProtoBuf definition.
@@ -139,6 +181,9 @@ impl QueryMethod {
             "QUERY_BY_MSGID" => Some(Self::QueryByMsgid),
             "QUERY_BY_TABLE_KEYID" => Some(Self::QueryByTableKeyid),
             "QUERY_STATUS" => Some(Self::QueryStatus),
+            "QUERY_PENDING" => Some(Self::QueryPending),
+            "QUERY_TOTAL_ORDER" => Some(Self::QueryTotalOrder),
+            "QUERY_STABLE_FRONTIER" => Some(Self::QueryStableFrontier),
             _ => None,
         }
     }
@@ -18,6 +18,67 @@ pub struct Innermsg {
     #[prost(bytes = "vec", repeated, tag = "6")]
     pub signatures: ::prost::alloc::vec::Vec<::prost::alloc::vec::Vec<u8>>,
 }
+/// ZMessage.data of an IDENTITY_INIT message, ZMessage.type = Z_TYPE_HANDSHAKE
+/// a write announces a node, the write reply answers with the receiver info
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct NodeHello {
+    #[prost(bytes = "vec", tag = "1")]
+    pub node_id: ::prost::alloc::vec::Vec<u8>,
+    /// x-only secp256k1 public key, equal to node_id
+    #[prost(bytes = "vec", tag = "2")]
+    pub public_key: ::prost::alloc::vec::Vec<u8>,
+    #[prost(uint32, tag = "3")]
+    pub protocol_version: u32,
+    /// current clock signed by the node key
+    #[prost(message, optional, tag = "4")]
+    pub clock_info: ::core::option::Option<super::vlc::ClockInfo>,
+    /// node id the hello is for, empty when not known yet
+    #[prost(bytes = "vec", tag = "5")]
+    pub to: ::prost::alloc::vec::Vec<u8>,
+    /// milliseconds, later than every previous hello of the node
+    #[prost(uint64, tag = "6")]
+    pub timestamp: u64,
+    /// node schnorr signature over the hello, see zebclock handshake
+    #[prost(bytes = "vec", tag = "7")]
+    pub signature: ::prost::alloc::vec::Vec<u8>,
+}
+/// piece of an encoded Innermsg too large for one datagram, sent after a 0x00
+/// mark byte, which can't start an Innermsg
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Fragment {
+    /// unique per sender
+    #[prost(uint64, tag = "1")]
+    pub message_id: u64,
+    #[prost(uint32, tag = "2")]
+    pub index: u32,
+    #[prost(uint32, tag = "3")]
+    pub count: u32,
+    /// bytes of the whole encoded Innermsg
+    #[prost(uint32, tag = "4")]
+    pub total_len: u32,
+    #[prost(bytes = "vec", tag = "5")]
+    pub data: ::prost::alloc::vec::Vec<u8>,
+}
+/// frame sent with acknowledgement, after a 0x01 mark byte
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Reliable {
+    /// unique per sender, acknowledged by the receiver
+    #[prost(uint64, tag = "1")]
+    pub message_id: u64,
+    /// encoded Innermsg
+    #[prost(bytes = "vec", tag = "2")]
+    pub frame: ::prost::alloc::vec::Vec<u8>,
+}
+/// acknowledged Reliable message ids, after a 0x02 mark byte
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Ack {
+    #[prost(uint64, repeated, tag = "1")]
+    pub message_ids: ::prost::alloc::vec::Vec<u64>,
+}
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
 #[repr(i32)]
 pub enum Identity {
@@ -3,6 +3,18 @@ pub mod vlc;
 pub mod bussiness;
 pub mod innermsg;
 
+impl From<u128> for vlc::Uint128 {
+    fn from(value: u128) -> Self {
+        Self { high: (value >> 64) as u64, low: value as u64 }
+    }
+}
+
+impl From<&vlc::Uint128> for u128 {
+    fn from(value: &vlc::Uint128) -> Self {
+        (u128::from(value.high) << 64) | u128::from(value.low)
+    }
+}
+
 pub fn add(left: usize, right: usize) -> usize {
     left + right
 }
@@ -13,7 +25,17 @@ mod tests {
 
     #[test]
     fn send_message() {
-        let mut msg = ZMessage::default();
-        msg.data = vec![1];
+        let msg = ZMessage {
+            data: vec![1],
+            ..Default::default()
+        };
+        assert_eq!(msg.data, vec![1]);
+    }
+
+    #[test]
+    fn uint128_roundtrip() {
+        for value in [0, 1, u128::from(u64::MAX) + 1, u128::MAX] {
+            assert_eq!(u128::from(&crate::vlc::Uint128::from(value)), value);
+        }
     }
 }
\ No newline at end of file
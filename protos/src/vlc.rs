@@ -1,9 +1,42 @@
 // This file is @generated by prost-build.
+/// event counters and clock values are u128, split into two halves
+/// the uint64 fields they replaced are reserved, their wire type differs
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Uint128 {
+    #[prost(uint64, tag = "1")]
+    pub high: u64,
+    #[prost(uint64, tag = "2")]
+    pub low: u64,
+}
+/// isolated event `counter` of node `id`
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Dot {
+    #[prost(string, tag = "1")]
+    pub id: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "2")]
+    pub counter: ::core::option::Option<Uint128>,
+}
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Clock {
-    #[prost(map = "string, uint64", tag = "1")]
-    pub values: ::std::collections::HashMap<::prost::alloc::string::String, u64>,
+    /// only for CLOCK_KIND_DOTTED_VERSION_VECTOR
+    #[prost(message, repeated, tag = "2")]
+    pub dots: ::prost::alloc::vec::Vec<Dot>,
+    #[prost(enumeration = "ClockKind", tag = "3")]
+    pub kind: i32,
+    #[prost(map = "string, message", tag = "4")]
+    pub counters: ::std::collections::HashMap<::prost::alloc::string::String, Uint128>,
+}
+/// physical time in milliseconds plus logical counter
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct HybridClock {
+    #[prost(uint64, tag = "1")]
+    pub physical: u64,
+    #[prost(uint32, tag = "2")]
+    pub logical: u32,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -28,10 +61,20 @@ pub struct ClockInfo {
     pub clock_hash: ::prost::alloc::vec::Vec<u8>,
     #[prost(bytes = "vec", tag = "4")]
     pub message_id: ::prost::alloc::vec::Vec<u8>,
-    #[prost(uint64, tag = "5")]
-    pub count: u64,
     #[prost(uint64, tag = "6")]
     pub create_at: u64,
+    #[prost(bytes = "vec", tag = "7")]
+    pub prev_clock_hash: ::prost::alloc::vec::Vec<u8>,
+    /// node schnorr signature, node_id is the x-only public key
+    #[prost(bytes = "vec", tag = "8")]
+    pub signature: ::prost::alloc::vec::Vec<u8>,
+    #[prost(message, optional, tag = "9")]
+    pub hlc: ::core::option::Option<HybridClock>,
+    /// signing payload version, 0 from older nodes: 2 with hlc, else 1
+    #[prost(uint32, tag = "10")]
+    pub sign_version: u32,
+    #[prost(message, optional, tag = "11")]
+    pub event_count: ::core::option::Option<Uint128>,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -40,16 +83,16 @@ pub struct MergeLog {
     pub from_id: ::prost::alloc::vec::Vec<u8>,
     #[prost(bytes = "vec", tag = "2")]
     pub to_id: ::prost::alloc::vec::Vec<u8>,
-    #[prost(uint64, tag = "3")]
-    pub start_count: u64,
-    #[prost(uint64, tag = "4")]
-    pub end_count: u64,
     #[prost(bytes = "vec", tag = "5")]
     pub s_clock_hash: ::prost::alloc::vec::Vec<u8>,
     #[prost(bytes = "vec", tag = "6")]
     pub e_clock_hash: ::prost::alloc::vec::Vec<u8>,
     #[prost(uint64, tag = "7")]
     pub merge_at: u64,
+    #[prost(message, optional, tag = "8")]
+    pub start_event_count: ::core::option::Option<Uint128>,
+    #[prost(message, optional, tag = "9")]
+    pub end_event_count: ::core::option::Option<Uint128>,
 }
 /// zmessage type = Z_TYPE_CLOCK
 #[allow(clippy::derive_partial_eq_without_eq)]
@@ -64,10 +107,27 @@ pub struct ZClock {
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct EventTrigger {
+    /// clock_info.clock is left out when clock_delta is set
     #[prost(message, optional, tag = "1")]
     pub clock_info: ::core::option::Option<ClockInfo>,
     #[prost(message, optional, tag = "2")]
     pub message: ::core::option::Option<super::zmessage::ZMessage>,
+    /// clock of the node the message was first written to
+    #[prost(message, optional, tag = "3")]
+    pub origin_clock: ::core::option::Option<ClockInfo>,
+    #[prost(message, optional, tag = "4")]
+    pub clock_delta: ::core::option::Option<ClockDelta>,
+}
+/// clock_info.clock as the changes against a clock the sender broadcast before
+/// and every live receiver acked in its heartbeat
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ClockDelta {
+    #[prost(bytes = "vec", tag = "1")]
+    pub base_clock_hash: ::prost::alloc::vec::Vec<u8>,
+    /// changed dimensions, 0 for a dropped one
+    #[prost(map = "string, message", tag = "2")]
+    pub values: ::std::collections::HashMap<::prost::alloc::string::String, Uint128>,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -82,12 +142,15 @@ pub struct DiffReq {
 pub struct DiffResp {
     #[prost(bytes = "vec", tag = "1")]
     pub to: ::prost::alloc::vec::Vec<u8>,
-    #[prost(uint64, tag = "2")]
-    pub start_index: u64,
     #[prost(message, repeated, tag = "3")]
     pub diffs: ::prost::alloc::vec::Vec<super::zmessage::ZMessage>,
     #[prost(message, optional, tag = "4")]
     pub from_clock: ::core::option::Option<ClockInfo>,
+    /// origin clock of each diff
+    #[prost(message, repeated, tag = "5")]
+    pub origins: ::prost::alloc::vec::Vec<ClockInfo>,
+    #[prost(message, optional, tag = "6")]
+    pub start_event_index: ::core::option::Option<Uint128>,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -98,6 +161,56 @@ pub struct ActiveSync {
     pub diffs: ::prost::alloc::vec::Vec<super::zmessage::ZMessage>,
     #[prost(message, optional, tag = "3")]
     pub latest: ::core::option::Option<ClockInfo>,
+    /// origin clock of each diff
+    #[prost(message, repeated, tag = "4")]
+    pub origins: ::prost::alloc::vec::Vec<ClockInfo>,
+}
+/// periodic gossip of the current signed clock
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ClockDigest {
+    #[prost(bytes = "vec", tag = "1")]
+    pub to: ::prost::alloc::vec::Vec<u8>,
+    #[prost(message, optional, tag = "2")]
+    pub clock_info: ::core::option::Option<ClockInfo>,
+}
+/// periodic liveness signal to every known peer, with the current signed clock
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Heartbeat {
+    #[prost(bytes = "vec", tag = "1")]
+    pub to: ::prost::alloc::vec::Vec<u8>,
+    #[prost(message, optional, tag = "2")]
+    pub clock_info: ::core::option::Option<ClockInfo>,
+    /// last event trigger clock received from node `to`, acked as clock delta base
+    #[prost(bytes = "vec", tag = "3")]
+    pub trigger_clock_hash: ::prost::alloc::vec::Vec<u8>,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum ClockKind {
+    Vector = 0,
+    DottedVersionVector = 1,
+}
+impl ClockKind {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            ClockKind::Vector => "CLOCK_KIND_VECTOR",
+            ClockKind::DottedVersionVector => "CLOCK_KIND_DOTTED_VERSION_VECTOR",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "CLOCK_KIND_VECTOR" => Some(Self::Vector),
+            "CLOCK_KIND_DOTTED_VERSION_VECTOR" => Some(Self::DottedVersionVector),
+            _ => None,
+        }
+    }
 }
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
 #[repr(i32)]
@@ -106,6 +219,8 @@ pub enum ClockType {
     DiffReq = 1,
     DiffRsp = 2,
     ActiveSync = 3,
+    Digest = 4,
+    Heartbeat = 5,
 }
 impl ClockType {
     /// String value of the enum field names used in the ProtoBuf definition.
@@ -118,6 +233,8 @@ impl ClockType {
             ClockType::DiffReq => "CLOCK_TYPE_DIFF_REQ",
             ClockType::DiffRsp => "CLOCK_TYPE_DIFF_RSP",
             ClockType::ActiveSync => "CLOCK_TYPE_ACTIVE_SYNC",
+            ClockType::Digest => "CLOCK_TYPE_DIGEST",
+            ClockType::Heartbeat => "CLOCK_TYPE_HEARTBEAT",
         }
     }
     /// Creates an enum from field names used in the ProtoBuf definition.
@@ -127,6 +244,8 @@ impl ClockType {
             "CLOCK_TYPE_DIFF_REQ" => Some(Self::DiffReq),
             "CLOCK_TYPE_DIFF_RSP" => Some(Self::DiffRsp),
             "CLOCK_TYPE_ACTIVE_SYNC" => Some(Self::ActiveSync),
+            "CLOCK_TYPE_DIGEST" => Some(Self::Digest),
+            "CLOCK_TYPE_HEARTBEAT" => Some(Self::Heartbeat),
             _ => None,
         }
     }
@@ -2,6 +2,7 @@
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ZMessage {
+    /// sha256 of the message encoded with empty id and signature
     #[prost(bytes = "vec", tag = "1")]
     pub id: ::prost::alloc::vec::Vec<u8>,
     #[prost(uint32, tag = "2")]
@@ -18,6 +19,9 @@ pub struct ZMessage {
     pub from: ::prost::alloc::vec::Vec<u8>,
     #[prost(bytes = "vec", tag = "8")]
     pub to: ::prost::alloc::vec::Vec<u8>,
+    /// of public_key and signature
+    #[prost(enumeration = "SignatureScheme", tag = "9")]
+    pub signature_scheme: i32,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -35,6 +39,34 @@ pub struct Rng {
     #[prost(bytes = "vec", tag = "3")]
     pub proof: ::prost::alloc::vec::Vec<u8>,
 }
+/// signature is made over the message encoded with an empty signature field
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum SignatureScheme {
+    /// 32 bytes x-only public key, sha256 digest
+    Secp256k1Schnorr = 0,
+    Ed25519 = 1,
+}
+impl SignatureScheme {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            SignatureScheme::Secp256k1Schnorr => "SIGNATURE_SCHEME_SECP256K1_SCHNORR",
+            SignatureScheme::Ed25519 => "SIGNATURE_SCHEME_ED25519",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "SIGNATURE_SCHEME_SECP256K1_SCHNORR" => Some(Self::Secp256k1Schnorr),
+            "SIGNATURE_SCHEME_ED25519" => Some(Self::Ed25519),
+            _ => None,
+        }
+    }
+}
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
 #[repr(i32)]
 pub enum ZType {
@@ -43,6 +75,7 @@ pub enum ZType {
     Clock = 2,
     Gateway = 3,
     Zchat = 4,
+    Handshake = 5,
 }
 impl ZType {
     /// String value of the enum field names used in the ProtoBuf definition.
@@ -56,6 +89,7 @@ impl ZType {
             ZType::Clock => "Z_TYPE_CLOCK",
             ZType::Gateway => "Z_TYPE_GATEWAY",
             ZType::Zchat => "Z_TYPE_ZCHAT",
+            ZType::Handshake => "Z_TYPE_HANDSHAKE",
         }
     }
     /// Creates an enum from field names used in the ProtoBuf definition.
@@ -66,6 +100,7 @@ impl ZType {
             "Z_TYPE_CLOCK" => Some(Self::Clock),
             "Z_TYPE_GATEWAY" => Some(Self::Gateway),
             "Z_TYPE_ZCHAT" => Some(Self::Zchat),
+            "Z_TYPE_HANDSHAKE" => Some(Self::Handshake),
             _ => None,
         }
     }
@@ -0,0 +1,64 @@
+//! secp256k1 schnorr helpers for node identities.
+//!
+//! A node id is the hex encoded x-only public key of the node secret key,
+//! so any peer can verify what a node signed from its id alone.
+
+use secp256k1::{schnorr::Signature, Keypair, Message, XOnlyPublicKey, SECP256K1};
+use sha2::{Digest, Sha256};
+
+pub use secp256k1::Keypair as NodeKeypair;
+
+/// Parse a hex secret key into a keypair.
+pub fn keypair_from_hex(secret_key: &str) -> Result<Keypair, secp256k1::Error> {
+    Keypair::from_seckey_str(SECP256K1, secret_key)
+}
+
+/// Generate a random keypair for a new node identity.
+pub fn generate_keypair() -> Keypair {
+    Keypair::new(SECP256K1, &mut secp256k1::rand::thread_rng())
+}
+
+/// Hex node id (x-only public key) of a keypair.
+pub fn node_id_of(keypair: &Keypair) -> String {
+    keypair.x_only_public_key().0.to_string()
+}
+
+/// Schnorr sign the sha256 digest of payload, returns the 64 bytes signature.
+pub fn sign_payload(keypair: &Keypair, payload: &[u8]) -> Vec<u8> {
+    let msg = Message::from_digest(Sha256::digest(payload).into());
+    SECP256K1.sign_schnorr_no_aux_rand(&msg, keypair).as_ref().to_vec()
+}
+
+/// Verify a schnorr signature made by `sign_payload` against an x-only public key.
+pub fn verify_payload(public_key: &[u8], payload: &[u8], signature: &[u8]) -> bool {
+    let (Ok(pubkey), Ok(sig)) = (XOnlyPublicKey::from_slice(public_key), Signature::from_slice(signature)) else {
+        return false;
+    };
+    let msg = Message::from_digest(Sha256::digest(payload).into());
+    SECP256K1.verify_schnorr(&sig, &msg, &pubkey).is_ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const SECRET_KEY: &str = "b8a0a5c1e8d5f8a9bd8ea2bb12d5bb7e4d5a9a3f0c8d1f7e6b5a4c3d2e1f0a9b";
+
+    #[test]
+    fn test_sign_verify_payload() {
+        let keypair = keypair_from_hex(SECRET_KEY).unwrap();
+        let node_id = node_id_of(&keypair);
+        assert_eq!(node_id.len(), 64);
+
+        let pubkey = keypair.x_only_public_key().0.serialize();
+        let sig = sign_payload(&keypair, b"payload");
+        assert_eq!(sig.len(), 64);
+        assert!(verify_payload(&pubkey, b"payload", &sig));
+        assert!(!verify_payload(&pubkey, b"payload2", &sig));
+        assert!(!verify_payload(&pubkey, b"payload", &sig[1..]));
+
+        let generated = generate_keypair();
+        assert_eq!(keypair_from_hex(&generated.display_secret().to_string()).unwrap(), generated);
+        assert_ne!(node_id_of(&generated), node_id);
+    }
+}
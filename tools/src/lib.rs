@@ -3,3 +3,4 @@
 pub mod tokio_zchronod;
 pub mod helper;
 pub mod rw_share;
+pub mod crypto;
@@ -0,0 +1,73 @@
+//! Merge and compare throughput of the vector clock keyed by hex node id
+//! strings against the compact clock over interned indices.
+//!
+//! Run with `cargo bench -p vlc`.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use vlc::{CompactClock, LogicalClock, NodeDictionary, VectorClock};
+
+const DIMENSIONS: [usize; 3] = [64, 1024, 4096];
+
+fn node_id(i: usize) -> String {
+    format!("{:064x}", i * 0x9e37_79b9)
+}
+
+/// Two clocks over `size` nodes where the first happened before the second,
+/// so comparing has to scan every dimension, as a string keyed vector clock
+/// and as a compact clock.
+fn clocks(size: usize) -> ((VectorClock<String>, VectorClock<String>), (CompactClock, CompactClock)) {
+    let mut dictionary = NodeDictionary::new();
+    let mut v1 = VectorClock::new();
+    let mut v2 = VectorClock::new();
+    for i in 0..size {
+        let id = node_id(i);
+        let (value1, value2) = ((i % 7 + 1) as u128, (i % 7 + 1 + i % 2) as u128);
+        v1.values.insert(id.clone(), value1);
+        v2.values.insert(id.clone(), value2);
+        dictionary.intern(&id);
+    }
+    let compact = |v: &VectorClock<String>| {
+        CompactClock::from_dims(v.values.iter().map(|(id, value)| (dictionary.index(id).unwrap(), *value)))
+    };
+    let (c1, c2) = (compact(&v1), compact(&v2));
+    ((v1, v2), (c1, c2))
+}
+
+fn bench_compare(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compare");
+    for size in DIMENSIONS {
+        let ((v1, v2), (c1, c2)) = clocks(size);
+        group.bench_with_input(BenchmarkId::new("vector", size), &size, |b, _| {
+            b.iter(|| black_box(&v1).partial_cmp(black_box(&v2)))
+        });
+        group.bench_with_input(BenchmarkId::new("compact", size), &size, |b, _| {
+            b.iter(|| black_box(&c1).partial_cmp(black_box(&c2)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_merge(c: &mut Criterion) {
+    let mut group = c.benchmark_group("merge");
+    for size in DIMENSIONS {
+        let ((v1, v2), (c1, c2)) = clocks(size);
+        group.bench_with_input(BenchmarkId::new("vector", size), &size, |b, _| {
+            b.iter(|| {
+                let mut clock = v1.clone();
+                clock.merge(&[black_box(&v2)]);
+                clock
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("compact", size), &size, |b, _| {
+            b.iter(|| {
+                let mut clock = c1.clone();
+                clock.merge(&[black_box(&c2)]);
+                clock
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_compare, bench_merge);
+criterion_main!(benches);
@@ -0,0 +1,292 @@
+//! Compact vector clock.
+//!
+//! Node ids are interned into small integers by a `NodeDictionary`, the clock
+//! keeps its non-zero dimensions as a vector of (index, value) sorted by
+//! index. Merge and compare are a single merge-join over both vectors, no
+//! hashing and no tree walks.
+
+use serde::{Deserialize, Serialize};
+use std::cmp;
+use std::collections::HashMap;
+
+use crate::{ClockKey, LogicalClock};
+
+impl ClockKey for u32 {
+    /// u32 big-endian.
+    fn encode_key(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_be_bytes());
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug, Default)]
+pub struct CompactClock {
+    dims: Vec<(u32, u128)>,
+}
+
+impl CompactClock {
+    /// Create a new clock.
+    pub fn new() -> Self {
+        Self { dims: Vec::new() }
+    }
+
+    /// Build a clock from (index, value) pairs in any order.
+    pub fn from_dims(dims: impl IntoIterator<Item = (u32, u128)>) -> Self {
+        let mut dims: Vec<(u32, u128)> = dims.into_iter().filter(|(_, v)| *v > 0).collect();
+        dims.sort_unstable();
+        // keep the largest value of a repeated index, the last one after sorting
+        dims.reverse();
+        dims.dedup_by_key(|(index, _)| *index);
+        dims.reverse();
+        Self { dims }
+    }
+
+    /// Non-zero dimensions sorted by index.
+    pub fn dims(&self) -> &[(u32, u128)] {
+        &self.dims
+    }
+
+    /// return common base clock of two clock
+    pub fn base_common(&self, other: &Self) -> Self {
+        let mut dims = Vec::with_capacity(cmp::min(self.dims.len(), other.dims.len()));
+        let (mut i, mut j) = (0, 0);
+        while i < self.dims.len() && j < other.dims.len() {
+            let ((k1, v1), (k2, v2)) = (self.dims[i], other.dims[j]);
+            match k1.cmp(&k2) {
+                cmp::Ordering::Less => i += 1,
+                cmp::Ordering::Greater => j += 1,
+                cmp::Ordering::Equal => {
+                    dims.push((k1, cmp::min(v1, v2)));
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+        Self { dims }
+    }
+}
+
+impl PartialOrd for CompactClock {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        let mut less = false;
+        let mut greater = false;
+        let (mut i, mut j) = (0, 0);
+        while i < self.dims.len() || j < other.dims.len() {
+            let (k1, v1) = self.dims.get(i).copied().unwrap_or((u32::MAX, 0));
+            let (k2, v2) = other.dims.get(j).copied().unwrap_or((u32::MAX, 0));
+            if i < self.dims.len() && (j == other.dims.len() || k1 < k2) {
+                greater = true;
+                i += 1;
+            } else if j < other.dims.len() && (i == self.dims.len() || k2 < k1) {
+                less = true;
+                j += 1;
+            } else {
+                greater |= v1 > v2;
+                less |= v1 < v2;
+                i += 1;
+                j += 1;
+            }
+            if less && greater {
+                return None;
+            }
+        }
+
+        if less {
+            Some(cmp::Ordering::Less)
+        } else if greater {
+            Some(cmp::Ordering::Greater)
+        } else {
+            Some(cmp::Ordering::Equal)
+        }
+    }
+}
+
+impl LogicalClock for CompactClock {
+    type Id = u32;
+
+    fn inc(&mut self, id: u32) {
+        match self.dims.binary_search_by_key(&id, |(index, _)| *index) {
+            Ok(pos) => self.dims[pos].1 += 1,
+            Err(pos) => self.dims.insert(pos, (id, 1)),
+        }
+    }
+
+    fn get(&self, id: &u32) -> u128 {
+        match self.dims.binary_search_by_key(id, |(index, _)| *index) {
+            Ok(pos) => self.dims[pos].1,
+            Err(_) => 0,
+        }
+    }
+
+    fn merge(&mut self, others: &[&Self]) {
+        for &other in others {
+            let mut dims = Vec::with_capacity(self.dims.len() + other.dims.len());
+            let (mut i, mut j) = (0, 0);
+            while i < self.dims.len() && j < other.dims.len() {
+                let ((k1, v1), (k2, v2)) = (self.dims[i], other.dims[j]);
+                match k1.cmp(&k2) {
+                    cmp::Ordering::Less => {
+                        dims.push((k1, v1));
+                        i += 1;
+                    }
+                    cmp::Ordering::Greater => {
+                        dims.push((k2, v2));
+                        j += 1;
+                    }
+                    cmp::Ordering::Equal => {
+                        dims.push((k1, cmp::max(v1, v2)));
+                        i += 1;
+                        j += 1;
+                    }
+                }
+            }
+            dims.extend_from_slice(&self.dims[i..]);
+            dims.extend_from_slice(&other.dims[j..]);
+            self.dims = dims;
+        }
+    }
+
+    /// Dimensions where self is ahead, zero dimensions are left out.
+    fn diff(&self, other: &Self) -> Self {
+        let dims = self.dims.iter()
+            .map(|(index, value)| (*index, value.saturating_sub(other.get(index))))
+            .filter(|(_, value)| *value > 0)
+            .collect();
+        Self { dims }
+    }
+
+    fn is_genesis(&self) -> bool {
+        self.dims.is_empty()
+    }
+
+    fn events(&self) -> u128 {
+        self.dims.iter().map(|(_, value)| value).sum()
+    }
+
+    /// Vector clock layout over u32 BE indices. Indices are local to a
+    /// dictionary, resolve them to node ids before comparing hashes across nodes.
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(5 + self.dims.len() * 20);
+        buf.push(crate::CLOCK_ENCODING_VERSION);
+        buf.extend_from_slice(&(self.dims.len() as u32).to_be_bytes());
+        for (index, value) in &self.dims {
+            index.encode_key(&mut buf);
+            buf.extend_from_slice(&value.to_be_bytes());
+        }
+        buf
+    }
+}
+
+/// Interns node ids into dense indices for compact clocks.
+#[derive(Debug, Clone, Default)]
+pub struct NodeDictionary {
+    indices: HashMap<String, u32>,
+    ids: Vec<String>,
+    saved: usize,
+}
+
+impl NodeDictionary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Index of `id`, assigning the next free one for a new id.
+    pub fn intern(&mut self, id: &str) -> u32 {
+        if let Some(index) = self.indices.get(id) {
+            return *index;
+        }
+        let index = self.ids.len() as u32;
+        self.indices.insert(id.to_owned(), index);
+        self.ids.push(id.to_owned());
+        index
+    }
+
+    pub fn index(&self, id: &str) -> Option<u32> {
+        self.indices.get(id).copied()
+    }
+
+    pub fn resolve(&self, index: u32) -> Option<&str> {
+        self.ids.get(index as usize).map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Restore persisted entries, which must continue the current indices.
+    /// Returns false and ignores the entry otherwise.
+    pub fn restore(&mut self, index: u32, id: &str) -> bool {
+        if index as usize != self.ids.len() || self.indices.contains_key(id) {
+            return false;
+        }
+        self.intern(id);
+        self.saved = self.ids.len();
+        true
+    }
+
+    /// Entries interned since the last `mark_saved`.
+    pub fn unsaved(&self) -> Vec<(u32, String)> {
+        (self.saved..self.ids.len()).map(|index| (index as u32, self.ids[index].clone())).collect()
+    }
+
+    /// Mark every entry up to `len` as persisted.
+    pub fn mark_saved(&mut self, len: usize) {
+        self.saved = cmp::max(self.saved, cmp::min(len, self.ids.len()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VectorClock;
+
+    #[test]
+    fn compact_matches_vector() {
+        let mut v1 = VectorClock::<u32>::new();
+        let mut v2 = VectorClock::<u32>::new();
+        let mut c1 = CompactClock::new();
+        let mut c2 = CompactClock::new();
+        for (id, n) in [(3, 2), (1, 1), (7, 3)] {
+            for _ in 0..n {
+                v1.inc(id);
+                c1.inc(id);
+            }
+        }
+        for (id, n) in [(1, 2), (5, 1)] {
+            for _ in 0..n {
+                v2.inc(id);
+                c2.inc(id);
+            }
+        }
+
+        assert_eq!(c1.partial_cmp(&c2), v1.partial_cmp(&v2));
+        assert_eq!(c1.partial_cmp(&c2), None);
+        assert_eq!(c1.encode(), v1.encode());
+        assert_eq!(c1.get(&7), 3);
+
+        c1.merge(&[&c2]);
+        v1.merge(&[&v2]);
+        assert_eq!(c1.encode(), v1.encode());
+        assert_eq!(c2.partial_cmp(&c1), Some(cmp::Ordering::Less));
+        assert_eq!(c1.events(), v1.events());
+        assert_eq!(c1.base_common(&c2), c2);
+        assert_eq!(c1.diff(&c2).dims(), &[(3, 2), (7, 3)]);
+        assert_eq!(CompactClock::from_dims([(7, 3), (1, 2), (3, 2), (5, 1), (9, 0), (1, 1)]), c1);
+    }
+
+    #[test]
+    fn dictionary_intern_restore() {
+        let mut dictionary = NodeDictionary::new();
+        assert!(dictionary.restore(0, "a"));
+        assert!(!dictionary.restore(2, "c"));
+        assert_eq!(dictionary.intern("b"), 1);
+        assert_eq!(dictionary.intern("a"), 0);
+        assert_eq!(dictionary.resolve(1), Some("b"));
+        assert_eq!(dictionary.unsaved(), vec![(1, "b".to_owned())]);
+        dictionary.mark_saved(2);
+        assert!(dictionary.unsaved().is_empty());
+    }
+}
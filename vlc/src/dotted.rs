@@ -0,0 +1,187 @@
+//! Dotted version vector.
+//!
+//! A version vector of the events seen without gaps per id, plus the set of
+//! isolated events ("dots") seen beyond them. Unlike a plain vector clock it
+//! doesn't claim events in a gap it never saw.
+
+use serde::{Deserialize, Serialize};
+use std::cmp;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{encode_dims, ClockKey, LogicalClock};
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct DottedVersionVector<K: Ord = u128> {
+    pub values: BTreeMap<K, u128>,
+    pub dots: BTreeSet<(K, u128)>,
+}
+
+impl<K: ClockKey> Default for DottedVersionVector<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: ClockKey> DottedVersionVector<K> {
+    /// Create a new clock.
+    pub fn new() -> Self {
+        Self {
+            values: BTreeMap::new(),
+            dots: BTreeSet::new(),
+        }
+    }
+
+    /// Return true if event `n` of `id` is known.
+    pub fn contains(&self, id: &K, n: u128) -> bool {
+        n <= self.get(id) || self.dots.contains(&(id.clone(), n))
+    }
+
+    /// Return true if every event known by self is known by other.
+    fn is_covered_by(&self, other: &Self) -> bool {
+        self.values.iter().all(|(id, value)| {
+            let covered = other.get(id);
+            *value <= covered || (covered + 1..=*value).all(|n| other.dots.contains(&(id.clone(), n)))
+        }) && self.dots.iter().all(|(id, n)| other.contains(id, *n))
+    }
+
+    /// Fold dots that became contiguous into the version vector.
+    fn normalize(&mut self) {
+        let dots = std::mem::take(&mut self.dots);
+        for (id, n) in dots {
+            let value = self.values.entry(id.clone()).or_insert(0);
+            if n == *value + 1 {
+                *value = n;
+            } else if n > *value {
+                self.dots.insert((id, n));
+            }
+        }
+    }
+
+    /// return common base clock of two clock
+    pub fn base_common(&self, other: &Self) -> Self {
+        let mut ret = Self::new();
+        for (id, v1) in &self.values {
+            ret.values.insert(id.clone(), cmp::min(*v1, other.get(id)));
+        }
+        ret.dots = self.dots.iter().filter(|(id, n)| other.contains(id, *n)).cloned().collect();
+        ret.normalize();
+        ret
+    }
+}
+
+impl<K: ClockKey> PartialOrd for DottedVersionVector<K> {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        match (self.is_covered_by(other), other.is_covered_by(self)) {
+            (true, true) => Some(cmp::Ordering::Equal),
+            (true, false) => Some(cmp::Ordering::Less),
+            (false, true) => Some(cmp::Ordering::Greater),
+            (false, false) => None,
+        }
+    }
+}
+
+impl<K: ClockKey> LogicalClock for DottedVersionVector<K> {
+    type Id = K;
+
+    fn inc(&mut self, id: K) {
+        let last_dot = self.dots.iter().filter(|(dot_id, _)| dot_id == &id).map(|(_, n)| *n).max();
+        let next = cmp::max(self.get(&id), last_dot.unwrap_or(0)) + 1;
+        self.dots.insert((id, next));
+        self.normalize();
+    }
+
+    fn get(&self, id: &K) -> u128 {
+        self.values.get(id).copied().unwrap_or(0)
+    }
+
+    fn merge(&mut self, others: &[&Self]) {
+        for &clock in others {
+            for (id, value) in &clock.values {
+                let v = self.values.entry(id.clone()).or_insert(0);
+                *v = cmp::max(*v, *value);
+            }
+            self.dots.extend(clock.dots.iter().cloned());
+        }
+        self.normalize();
+    }
+
+    /// Version vector part as in `VectorClock::diff`, plus the dots other misses.
+    fn diff(&self, other: &Self) -> Self {
+        let mut ret = Self::new();
+        for (id, v1) in &self.values {
+            ret.values.insert(id.clone(), v1.saturating_sub(other.get(id)));
+        }
+        ret.dots = self.dots.iter().filter(|(id, n)| !other.contains(id, *n)).cloned().collect();
+        ret
+    }
+
+    fn is_genesis(&self) -> bool {
+        self.dots.is_empty() && self.values.values().all(|v| *v == 0)
+    }
+
+    fn events(&self) -> u128 {
+        self.values.values().sum::<u128>() + self.dots.len() as u128
+    }
+
+    /// Layout: the vector clock encoding of the version vector, then u32 BE
+    /// dot count and every dot sorted as (encoded id, u128 BE event number).
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(9 + (self.values.len() + self.dots.len()) * 32);
+        encode_dims(&self.values, &mut buf);
+        buf.extend_from_slice(&(self.dots.len() as u32).to_be_bytes());
+        for (id, n) in &self.dots {
+            id.encode_key(&mut buf);
+            buf.extend_from_slice(&n.to_be_bytes());
+        }
+        buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dotted_gap_and_fold() {
+        let mut c1 = DottedVersionVector::<u128>::new();
+        c1.inc(0);
+        c1.inc(0);
+
+        // c2 only saw the second event of node 0
+        let mut c2 = DottedVersionVector::<u128>::new();
+        c2.dots.insert((0, 2));
+        assert_eq!(c2.get(&0), 0);
+        assert!(c2.contains(&0, 2));
+        assert_eq!(c2.partial_cmp(&c1), Some(cmp::Ordering::Less));
+
+        let mut c3 = DottedVersionVector::<u128>::new();
+        c3.inc(1);
+        assert_eq!(c2.partial_cmp(&c3), None);
+
+        c2.merge(&[&c3]);
+        assert_eq!(c2.partial_cmp(&c1), None);
+
+        // the missing event fills the gap, the dot folds into the vector
+        let mut first = DottedVersionVector::<u128>::new();
+        first.dots.insert((0, 1));
+        c2.merge(&[&first]);
+        assert!(c2.dots.is_empty());
+        assert_eq!(c2.get(&0), 2);
+        assert_eq!(c2.partial_cmp(&c1), Some(cmp::Ordering::Greater));
+        assert_eq!(c1.diff(&c2).dots.len(), 0);
+        assert_eq!(c2.base_common(&c1).partial_cmp(&c1), Some(cmp::Ordering::Equal));
+    }
+
+    #[test]
+    fn dotted_encode_hash() {
+        let mut c1 = DottedVersionVector::<String>::new();
+        c1.inc("a".to_owned());
+        let mut c2 = c1.clone();
+        c2.values.insert("b".to_owned(), 0);
+        assert_eq!(c1.hash_hex(), c2.hash_hex());
+
+        c2.dots.insert(("a".to_owned(), 3));
+        assert_ne!(c1.hash_hex(), c2.hash_hex());
+        assert!(c2.encode().starts_with(&crate::VectorClock { values: c1.values.clone() }.encode()));
+    }
+}
@@ -0,0 +1,89 @@
+//! Hybrid logical clock.
+//!
+//! Physical time in milliseconds plus a logical counter that orders events
+//! within the same millisecond. It stays close to wall-clock time while
+//! still respecting happened-before: an event received from a peer is
+//! always stamped after the peer event.
+
+use serde::{Deserialize, Serialize};
+use std::cmp;
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Default)]
+pub struct HybridLogicalClock {
+    pub physical: u64,
+    pub logical: u32,
+}
+
+impl HybridLogicalClock {
+    pub fn new(physical: u64, logical: u32) -> Self {
+        Self { physical, logical }
+    }
+
+    /// Stamp a local event at wall time `wall`.
+    pub fn tick(&mut self, wall: u64) {
+        if wall > self.physical {
+            self.physical = wall;
+            self.logical = 0;
+        } else {
+            self.logical += 1;
+        }
+    }
+
+    /// Stamp the receipt of an event stamped `remote` at wall time `wall`.
+    pub fn observe(&mut self, remote: &HybridLogicalClock, wall: u64) {
+        let physical = cmp::max(cmp::max(self.physical, remote.physical), wall);
+        self.logical = if physical == self.physical && physical == remote.physical {
+            cmp::max(self.logical, remote.logical) + 1
+        } else if physical == self.physical {
+            self.logical + 1
+        } else if physical == remote.physical {
+            remote.logical + 1
+        } else {
+            0
+        };
+        self.physical = physical;
+    }
+
+    /// Return true if the clock runs more than `max_drift` milliseconds ahead of `wall`.
+    pub fn is_ahead_of(&self, wall: u64, max_drift: u64) -> bool {
+        self.physical > wall.saturating_add(max_drift)
+    }
+
+    /// Canonical encoding: u64 BE physical time, u32 BE logical counter.
+    pub fn encode(&self) -> [u8; 12] {
+        let mut buf = [0u8; 12];
+        buf[..8].copy_from_slice(&self.physical.to_be_bytes());
+        buf[8..].copy_from_slice(&self.logical.to_be_bytes());
+        buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hlc_tick_observe() {
+        let mut c1 = HybridLogicalClock::default();
+        c1.tick(100);
+        assert_eq!(c1, HybridLogicalClock::new(100, 0));
+
+        // wall clock went backwards, the logical counter keeps the order
+        let before = c1;
+        c1.tick(90);
+        assert_eq!(c1, HybridLogicalClock::new(100, 1));
+        assert!(before < c1);
+
+        // a peer ahead of us pushes our physical time forward
+        let remote = HybridLogicalClock::new(120, 3);
+        c1.observe(&remote, 110);
+        assert_eq!(c1, HybridLogicalClock::new(120, 4));
+        assert!(remote < c1);
+
+        c1.observe(&HybridLogicalClock::new(50, 0), 130);
+        assert_eq!(c1, HybridLogicalClock::new(130, 0));
+
+        assert!(!c1.is_ahead_of(100, 30));
+        assert!(c1.is_ahead_of(100, 29));
+    }
+}
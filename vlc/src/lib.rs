@@ -5,30 +5,124 @@
 //! network can verify the correctness of the clock.
 
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::cmp;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
+pub mod compact;
+pub mod dotted;
+pub mod hlc;
+
+pub use compact::{CompactClock, NodeDictionary};
+pub use dotted::DottedVersionVector;
+pub use hlc::HybridLogicalClock;
+
+/// Version byte prefixed to every canonical clock encoding.
+pub const CLOCK_ENCODING_VERSION: u8 = 1;
+
+/// Key of a clock dimension, usually a node id.
+pub trait ClockKey: Ord + Clone {
+    /// Append the canonical encoding of the key to `buf`.
+    fn encode_key(&self, buf: &mut Vec<u8>);
+}
+
+impl ClockKey for u128 {
+    /// u128 big-endian.
+    fn encode_key(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_be_bytes());
+    }
+}
+
+impl ClockKey for String {
+    /// u32 big-endian byte length, then the utf-8 bytes.
+    fn encode_key(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&(self.len() as u32).to_be_bytes());
+        buf.extend_from_slice(self.as_bytes());
+    }
+}
+
+/// Operations every logical clock implementation provides. Comparison is
+/// the `PartialOrd` of the clock, `None` means concurrent.
+pub trait LogicalClock: Clone + Default + PartialOrd {
+    type Id: ClockKey;
+
+    /// Record a new event of `id`.
+    fn inc(&mut self, id: Self::Id);
+
+    /// Number of events of `id` known without gaps.
+    fn get(&self, id: &Self::Id) -> u128;
+
+    /// Merge the clock with other clocks.
+    fn merge(&mut self, others: &[&Self]);
+
+    /// Diff is local clock minus another clock.
+    fn diff(&self, other: &Self) -> Self;
+
+    /// Return true when the clock holds no event.
+    fn is_genesis(&self) -> bool;
+
+    /// Total number of events the clock knows. It strictly grows along
+    /// happened-before, which makes it a causal sort key.
+    fn events(&self) -> u128;
+
+    /// Canonical byte encoding of the clock, equal clocks encode equally.
+    fn encode(&self) -> Vec<u8>;
+
+    /// Sha256 of the canonical encoding.
+    fn hash(&self) -> [u8; 32] {
+        Sha256::digest(self.encode()).into()
+    }
+
+    /// Hex string of the canonical clock hash.
+    fn hash_hex(&self) -> String {
+        format!("{:x}", Sha256::digest(self.encode()))
+    }
+}
+
+/// Deterministic total order extending the causal order: clocks that
+/// happened before sort first, concurrent clocks are ordered by event total
+/// and then by canonical clock hash, so every node gets the same sequence.
+pub fn total_cmp<C: LogicalClock>(a: &C, b: &C) -> cmp::Ordering {
+    a.events().cmp(&b.events()).then_with(|| a.hash().cmp(&b.hash()))
+}
+
+/// Append version byte, u32 BE dimension count and every non-zero
+/// dimension as (key, u128 BE value) in key order.
+fn encode_dims<K: ClockKey>(values: &BTreeMap<K, u128>, buf: &mut Vec<u8>) {
+    let dims: Vec<(&K, &u128)> = values.iter().filter(|(_, v)| **v > 0).collect();
+    buf.push(CLOCK_ENCODING_VERSION);
+    buf.extend_from_slice(&(dims.len() as u32).to_be_bytes());
+    for (id, value) in dims {
+        id.encode_key(buf);
+        buf.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+/// Vector clock keyed by any clock key.
 #[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
-pub struct Clock {
-    values: HashMap<u128, u128>,
+pub struct VectorClock<K: Ord = u128> {
+    pub values: BTreeMap<K, u128>,
 }
 
-impl PartialOrd for Clock {
-    fn partial_cmp(&self, other: &Clock) -> Option<cmp::Ordering> {
+/// Vector clock keyed by numeric node index.
+pub type Clock = VectorClock<u128>;
+
+impl<K: ClockKey> PartialOrd for VectorClock<K> {
+    fn partial_cmp(&self, other: &VectorClock<K>) -> Option<cmp::Ordering> {
         let mut less = false;
         let mut greater = false;
 
         for (id, value) in &self.values {
-            let other_value = other.values.get(id);
-            if other_value.is_none() || value > other_value.unwrap() {
+            let other_value = other.values.get(id).unwrap_or(&0);
+            if value > other_value {
                 greater = true;
-            } else if value < other_value.unwrap() {
+            } else if value < other_value {
                 less = true;
             }
         }
 
-        for id in other.values.keys() {
-            if !self.values.contains_key(id) {
+        for (id, value) in &other.values {
+            if !self.values.contains_key(id) && *value > 0 {
                 less = true;
             }
         }
@@ -45,61 +139,37 @@ impl PartialOrd for Clock {
     }
 }
 
-impl Default for Clock {
+impl<K: ClockKey> Default for VectorClock<K> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl Clock {
+impl<K: ClockKey> VectorClock<K> {
     /// Create a new clock.
     pub fn new() -> Self {
         Self {
-            values: HashMap::new(),
+            values: BTreeMap::new(),
         }
     }
 
-    /// Increment the clock
-    pub fn inc(&mut self, id: u128) {
-        let value = self.values.entry(id).or_insert(0);
-        *value += 1;
-    }
-
-    /// Get the clock count by id
-    pub fn get(&mut self, id: u128) -> u128 {
-        let value = self.values.entry(id).or_insert(0);
-        *value
-    }
-
     /// Reset the clock.
     pub fn clear(&mut self) {
         self.values.clear();
     }
 
-    /// Merge the clock with other clocks.
-    pub fn merge(&mut self, others: &Vec<&Clock>) {
-        for &clock in others {
-            for (id, value) in &clock.values {
-                let v = self.values.entry(*id).or_insert(0);
-                *v = std::cmp::max(*v, *value);
-            }
-        }
-    }
-
-    /// Diff is local clock minus another clock
-    pub fn diff(&self, other: &Clock) -> Clock {
-        let mut ret = Clock::new();
+    /// return common base clock of two clock
+    pub fn base_common(&self, other: &Self) -> Self {
+        let mut ret = Self::new();
         for (id, v1) in &self.values {
             let v2 = other.values.get(id).unwrap_or(&0);
-            if v1 > v2 {
-                ret.values.insert(*id, v1-v2);
-            } else {
-                ret.values.insert(*id, 0);
-            }
+            ret.values.insert(id.clone(), cmp::min(*v1, *v2));
         }
         ret
     }
+}
 
+impl<K: ClockKey + std::fmt::Display> VectorClock<K> {
     /// return index key of clock
     pub fn index_key(&self) -> String {
         let mut key: String = String::new();
@@ -108,27 +178,54 @@ impl Clock {
         }
         key
     }
+}
 
-    /// return common base clock of two clock
-    pub fn base_common(&self, other: &Clock) -> Clock {
-        let mut ret = Clock::new();
+impl<K: ClockKey> LogicalClock for VectorClock<K> {
+    type Id = K;
+
+    fn inc(&mut self, id: K) {
+        let value = self.values.entry(id).or_insert(0);
+        *value += 1;
+    }
+
+    fn get(&self, id: &K) -> u128 {
+        self.values.get(id).copied().unwrap_or(0)
+    }
+
+    fn merge(&mut self, others: &[&Self]) {
+        for &clock in others {
+            for (id, value) in &clock.values {
+                let v = self.values.entry(id.clone()).or_insert(0);
+                *v = std::cmp::max(*v, *value);
+            }
+        }
+    }
+
+    fn diff(&self, other: &Self) -> Self {
+        let mut ret = Self::new();
         for (id, v1) in &self.values {
             let v2 = other.values.get(id).unwrap_or(&0);
-            if v1 <= v2 {
-                ret.values.insert(*id, *v1);
-            } else {
-                ret.values.insert(*id, *v2);
-            }
+            ret.values.insert(id.clone(), v1.saturating_sub(*v2));
         }
         ret
     }
 
-    /// return true when all value is zero in clock dimensions
-    pub fn is_genesis(&self) -> bool {
-        let sum: u128 = self.values.values().sum();
-        sum == 0
+    fn is_genesis(&self) -> bool {
+        self.values.values().all(|v| *v == 0)
+    }
+
+    fn events(&self) -> u128 {
+        self.values.values().sum()
+    }
+
+    /// Layout: version byte, u32 big-endian dimension count, then every
+    /// non-zero dimension sorted by id as (encoded id, u128 BE value).
+    /// Zero dimensions are skipped so they don't change the encoding.
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(5 + self.values.len() * 32);
+        encode_dims(&self.values, &mut buf);
+        buf
     }
-    
 }
 
 #[cfg(test)]
@@ -175,13 +272,59 @@ mod tests {
         assert_eq!(c1.partial_cmp(&c3), None);
         assert_eq!(c2.partial_cmp(&c3), None);
 
-        c1.merge(&vec![&c2, &c3]);
+        c1.merge(&[&c2, &c3]);
         assert_eq!(c2.partial_cmp(&c1), Some(cmp::Ordering::Less));
         assert_eq!(c1.partial_cmp(&c2), Some(cmp::Ordering::Greater));
         assert_eq!(c3.partial_cmp(&c1), Some(cmp::Ordering::Less));
         assert_eq!(c1.partial_cmp(&c3), Some(cmp::Ordering::Greater));
     }
 
+    #[test]
+    fn clock_encode_hash() {
+        let mut c1 = Clock::new();
+        c1.inc(2);
+        c1.inc(1);
+        c1.inc(1);
+        let mut c2 = Clock::new();
+        c2.inc(1);
+        c2.inc(2);
+        c2.inc(1);
+        c2.values.insert(3, 0);
+
+        assert_eq!(c1.partial_cmp(&c2), Some(cmp::Ordering::Equal));
+        assert_eq!(c1.encode(), c2.encode());
+        assert_eq!(c1.hash_hex(), c2.hash_hex());
+
+        let encoded = c1.encode();
+        assert_eq!(encoded.len(), 5 + 2 * 32);
+        assert_eq!(encoded[0], CLOCK_ENCODING_VERSION);
+        assert_eq!(&encoded[1..5], &2u32.to_be_bytes());
+        assert_eq!(&encoded[5..21], &1u128.to_be_bytes());
+        assert_eq!(&encoded[21..37], &2u128.to_be_bytes());
+
+        c2.inc(3);
+        assert_ne!(c1.hash(), c2.hash());
+    }
+
+    #[test]
+    fn clock_total_order() {
+        let mut c1 = Clock::new();
+        c1.inc(0);
+        let mut c2 = Clock::new();
+        c2.inc(1);
+        let mut c3 = c1.clone();
+        c3.merge(&[&c2]);
+        c3.inc(2);
+
+        let mut clocks = vec![c3.clone(), c2.clone(), c1.clone()];
+        clocks.sort_by(total_cmp);
+        let mut reversed = vec![c1.clone(), c2.clone(), c3.clone()];
+        reversed.sort_by(total_cmp);
+        assert_eq!(clocks, reversed);
+        assert_eq!(clocks[2], c3);
+        assert_eq!(total_cmp(&c1, &c2), c1.hash().cmp(&c2.hash()));
+    }
+
     #[test]
     #[ignore]
     fn stress_update() -> std::fmt::Result<> {
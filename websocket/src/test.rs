@@ -1,4 +1,4 @@
-/// zchronod_websocket test
+//! zchronod_websocket test
 
 use crate::*;
 
@@ -0,0 +1,112 @@
+//! Threshold endorsements of server messages.
+//!
+//! Each entry of `Innermsg.public_keys` is an x-only secp256k1 key whose
+//! schnorr signature over the message without endorsements sits at the same
+//! index of `Innermsg.signatures`. The sending node endorses what it sends,
+//! replacing the endorsements of the message it answers. A receiver accepts a
+//! server message once enough distinct signers of its signer set endorsed it.
+//! Nothing collects co-signatures yet, so the config allows a threshold of 1.
+
+use std::collections::HashSet;
+use prost::Message;
+use protos::innermsg::Innermsg;
+use tools::crypto::{sign_payload, verify_payload, NodeKeypair};
+use tracing::*;
+use crate::zchronod::ZchronodArc;
+
+/// Bytes an endorsement signature is made over.
+pub fn endorsement_payload(inner: &Innermsg) -> Vec<u8> {
+    Innermsg { public_keys: Vec::new(), signatures: Vec::new(), ..inner.clone() }.encode_to_vec()
+}
+
+/// Add the endorsement of `keypair`, once per signer.
+pub fn endorse(keypair: &NodeKeypair, inner: &mut Innermsg) {
+    let public_key = keypair.x_only_public_key().0.serialize().to_vec();
+    if inner.public_keys.contains(&public_key) {
+        return;
+    }
+    let signature = sign_payload(keypair, &endorsement_payload(inner));
+    inner.public_keys.push(public_key);
+    inner.signatures.push(signature);
+}
+
+/// Number of distinct signers accepted by `is_signer` with a valid endorsement.
+pub fn count_endorsements(inner: &Innermsg, is_signer: impl Fn(&str) -> bool) -> usize {
+    let payload = endorsement_payload(inner);
+    let mut signers = HashSet::new();
+    for (public_key, signature) in inner.public_keys.iter().zip(inner.signatures.iter()) {
+        let node_id = hex::encode(public_key);
+        if !signers.contains(&node_id) && is_signer(&node_id) && verify_payload(public_key, &payload, signature) {
+            signers.insert(node_id);
+        }
+    }
+    signers.len()
+}
+
+/// Replace the endorsements of a server message this node built with its own.
+pub async fn sign_srv_msg(arc_zchronod: &ZchronodArc, inner: &mut Innermsg) {
+    inner.public_keys.clear();
+    inner.signatures.clear();
+    let keypair = arc_zchronod.state.read().await.keypair;
+    endorse(&keypair, inner);
+}
+
+/// Return false if a server message lacks the endorsements the config asks
+/// for from the configured signers.
+pub async fn check_srv_endorsements(arc_zchronod: &ZchronodArc, inner: &Innermsg) -> bool {
+    let threshold = arc_zchronod.config.node.endorsement_threshold;
+    if threshold == 0 {
+        return true;
+    }
+    // validate_config requires at least threshold signers, so peers can't add keys of their own
+    let configured = &arc_zchronod.config.node.endorsement_signers;
+    let endorsed = count_endorsements(inner, |node_id| configured.iter().any(|signer| signer.eq_ignore_ascii_case(node_id)));
+    if endorsed < threshold {
+        warn!("Reject server message: endorsed by {} of {} required signers", endorsed, threshold);
+        return false;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protos::innermsg::{Action, Identity};
+    use protos::zmessage::ZMessage;
+    use tools::crypto::{keypair_from_hex, node_id_of};
+
+    #[test]
+    fn threshold_endorsements() {
+        let keypairs: Vec<NodeKeypair> = [
+            "b8a0a5c1e8d5f8a9bd8ea2bb12d5bb7e4d5a9a3f0c8d1f7e6b5a4c3d2e1f0a9b",
+            "5b3e8f2d1c7a4b6e9f0d2c8a7b5e3f1d4c6a8b0e2f4d6c8a1b3e5f7d9c0a2b4e",
+            "c3d2e1f0a9b8a0a5c1e8d5f8a9bd8ea2bb12d5bb7e4d5a9a3f0c8d1f7e6b5a4d",
+        ].iter().map(|secret_key| keypair_from_hex(secret_key).unwrap()).collect();
+        let signers: Vec<String> = keypairs[..2].iter().map(node_id_of).collect();
+        let is_signer = |node_id: &str| signers.iter().any(|signer| signer == node_id);
+
+        let mut inner = Innermsg {
+            identity: Identity::Server.into(),
+            action: Action::WriteReply.into(),
+            message: Some(ZMessage { data: b"trigger".to_vec(), ..Default::default() }),
+            ..Default::default()
+        };
+        endorse(&keypairs[0], &mut inner);
+        endorse(&keypairs[0], &mut inner);
+        assert_eq!(inner.signatures.len(), 1);
+        // not in the signer set
+        endorse(&keypairs[2], &mut inner);
+        assert_eq!(count_endorsements(&inner, is_signer), 1);
+        endorse(&keypairs[1], &mut inner);
+        assert_eq!(count_endorsements(&inner, is_signer), 2);
+
+        // a repeated key counts once
+        inner.public_keys.push(inner.public_keys[0].clone());
+        inner.signatures.push(inner.signatures[0].clone());
+        assert_eq!(count_endorsements(&inner, is_signer), 2);
+
+        let mut tampered = inner.clone();
+        tampered.action = Action::Write.into();
+        assert_eq!(count_endorsements(&tampered, is_signer), 0);
+    }
+}
@@ -0,0 +1,213 @@
+//! Node handshake.
+//!
+//! A joining node sends an `Identity::Init` write carrying its `NodeHello`:
+//! node id, public key, protocol version and signed current clock, with a
+//! timestamp signed together with them. The receiver checks it, records the
+//! node as a peer and answers with its own hello as a write reply. Both sides
+//! then catch up the one that is behind.
+//!
+//! A hello is accepted once: a stale one, or one not later than the last
+//! accepted hello of its node, is a replay and can't move the node to the
+//! address it came from.
+
+use std::cmp;
+use std::net::SocketAddr;
+use prost::Message;
+use protos::innermsg::{Action, Identity, Innermsg, NodeHello, PushType};
+use protos::zmessage::{ZMessage, ZType};
+use p2p::Link;
+use crate::vlc::{ClockInfo, LogicalClock};
+use crate::zchronod::ZchronodArc;
+use tools::crypto::{sign_payload, verify_payload};
+use tracing::*;
+
+use super::response::clockinfo_to_proto;
+use super::sync::{active_sync, request_diff};
+
+/// Version of the server to server protocol, nodes of another version are refused.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Domain tag prefixed to the hello signing payload.
+pub const HELLO_SIGN_DOMAIN: &[u8] = b"zebclock/node_hello/v1";
+
+/// Milliseconds a hello stays acceptable after its timestamp.
+pub const HELLO_MAX_AGE: u64 = 30_000;
+
+/// Bytes covered by the hello signature: domain tag, node id, receiver node
+/// id, protocol version, timestamp and hash of the carried clock. Variable
+/// length parts are u32 BE length prefixed, numbers are BE.
+pub fn hello_payload(hello: &NodeHello) -> Vec<u8> {
+    let clock_hash = hello.clock_info.as_ref().map_or_else(Vec::new, |clock_info| ClockInfo::from(clock_info).clock.hash().to_vec());
+    let mut buf = HELLO_SIGN_DOMAIN.to_vec();
+    for part in [&hello.node_id, &hello.to, &clock_hash] {
+        buf.extend_from_slice(&(part.len() as u32).to_be_bytes());
+        buf.extend_from_slice(part);
+    }
+    buf.extend_from_slice(&hello.protocol_version.to_be_bytes());
+    buf.extend_from_slice(&hello.timestamp.to_be_bytes());
+    buf
+}
+
+/// Send our hello to the relay at `dest` on `link`, `Action::Write` to
+/// announce this node, `Action::WriteReply` to answer a hello.
+pub async fn send_hello(arc_zchronod: ZchronodArc, action: Action, to: &[u8], link: Link, dest: SocketAddr) {
+    let node_id = hex::decode(&arc_zchronod.node_id).unwrap_or_else(|_| Vec::new());
+    let (clock_info, keypair) = {
+        let state = arc_zchronod.state.read().await;
+        (clockinfo_to_proto()(state.clock_info.clone()), state.keypair)
+    };
+    let mut hello = NodeHello {
+        node_id: node_id.clone(),
+        public_key: node_id.clone(),
+        protocol_version: PROTOCOL_VERSION,
+        clock_info: Some(clock_info),
+        to: to.to_vec(),
+        timestamp: tools::helper::get_time_ms().try_into().unwrap_or(u64::MAX),
+        signature: Vec::new(),
+    };
+    hello.signature = sign_payload(&keypair, &hello_payload(&hello));
+    let p2p_msg = ZMessage {
+        r#type: ZType::Handshake.into(),
+        from: node_id,
+        to: to.to_vec(),
+        data: hello.encode_to_vec(),
+        ..Default::default()
+    };
+    let inner = Innermsg {
+        identity: Identity::Init.into(),
+        action: action.into(),
+        push_type: PushType::Direct.into(),
+        message: Some(p2p_msg),
+        ..Default::default()
+    };
+    info!("Send handshake: action = {:?}, dest = {}", action, dest);
+    if let Err(err) = arc_zchronod.network.send(link, &inner, dest).await {
+        error!("Send handshake to {} failed, err={}", dest, err);
+    }
+}
+
+pub async fn handle_init_msg(arc_zchronod: ZchronodArc, inner_msg: Innermsg, p2p_msg: &ZMessage, link: Link, src: SocketAddr) {
+    let hello = match NodeHello::decode(prost::bytes::Bytes::from(p2p_msg.data.clone())) {
+        Err(err) => {
+            error!("Decode NodeHello error, err={:?}", err);
+            return;
+        }
+        Ok(hello) => hello,
+    };
+    let peer = match check_hello(&arc_zchronod, &hello) {
+        Err(reason) => {
+            warn!("Reject handshake from {}: {}", src, reason);
+            return;
+        }
+        Ok(peer) => peer,
+    };
+    if let Err(reason) = accept_hello_time(&arc_zchronod, &peer.node_id, hello.timestamp).await {
+        warn!("Reject handshake from {}: {}", src, reason);
+        return;
+    }
+    arc_zchronod.note_peer(&peer, src).await;
+    arc_zchronod.move_peer(&peer.node_id, src).await;
+    info!("Handshake with node {} at {}", peer.node_id, src);
+
+    if inner_msg.action() == Action::Write {
+        send_hello(arc_zchronod.clone(), Action::WriteReply, &hello.node_id, link, src).await;
+    }
+    let ordering = arc_zchronod.state.read().await.compare(&peer.clock);
+    match ordering {
+        Some(cmp::Ordering::Less) => request_diff(arc_zchronod, &peer.node_id, link, src).await,
+        None => active_sync(arc_zchronod, &peer, link, src).await,
+        // the peer pulls from our hello if it is behind
+        _ => {}
+    }
+}
+
+/// Validate a hello, returns the verified clock of the node.
+fn check_hello(arc_zchronod: &ZchronodArc, hello: &NodeHello) -> Result<ClockInfo, String> {
+    if hello.protocol_version != PROTOCOL_VERSION {
+        return Err(format!("protocol version {} instead of {}", hello.protocol_version, PROTOCOL_VERSION));
+    }
+    let node_id = hex::encode(&hello.node_id);
+    if !tools::helper::validate_nodeid(&node_id) || hello.public_key != hello.node_id {
+        return Err(format!("node id {} is not its public key", node_id));
+    }
+    if node_id == arc_zchronod.node_id {
+        return Err("hello from this node".to_owned());
+    }
+    if !hello.to.is_empty() && hex::encode(&hello.to) != arc_zchronod.node_id {
+        return Err(format!("hello for node {}", hex::encode(&hello.to)));
+    }
+    if !verify_payload(&hello.node_id, &hello_payload(hello), &hello.signature) {
+        return Err(format!("hello not signed by node {}", node_id));
+    }
+    let Some(clock_info) = hello.clock_info.as_ref().map(ClockInfo::from) else {
+        return Err("hello without clock".to_owned());
+    };
+    if clock_info.node_id != node_id || !clock_info.verify_signature() {
+        return Err(format!("clock not signed by node {}", node_id));
+    }
+    if !arc_zchronod.within_drift(&clock_info) {
+        return Err("hybrid clock too far ahead".to_owned());
+    }
+    Ok(clock_info)
+}
+
+/// Accept the timestamp of a verified hello of node `node_id` once: it must
+/// be recent, not ahead of local time beyond the clock drift, and later than
+/// the last accepted one.
+async fn accept_hello_time(arc_zchronod: &ZchronodArc, node_id: &str, timestamp: u64) -> Result<(), String> {
+    let now: u64 = tools::helper::get_time_ms().try_into().unwrap_or(u64::MAX);
+    if timestamp > now.saturating_add(arc_zchronod.config.node.max_clock_drift) {
+        return Err(format!("hello {} ms ahead of local time", timestamp - now));
+    }
+    if now.saturating_sub(timestamp) > HELLO_MAX_AGE {
+        return Err(format!("stale hello from {} ms ago", now - timestamp));
+    }
+    let mut hello_times = arc_zchronod.hello_times.lock().await;
+    // older entries can't be replayed anymore, the age check rejects them
+    hello_times.retain(|_, last| now.saturating_sub(*last) <= HELLO_MAX_AGE);
+    if hello_times.get(node_id).is_some_and(|last| timestamp <= *last) {
+        return Err("replayed hello".to_owned());
+    }
+    hello_times.insert(node_id.to_owned(), timestamp);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vlc::Clock;
+    use tools::crypto::{keypair_from_hex, node_id_of};
+
+    #[test]
+    fn hello_signature_covers_receiver_time_and_clock() {
+        let keypair = keypair_from_hex("5b3e8f2d1c7a4b6e9f0d2c8a7b5e3f1d4c6a8b0e2f4d6c8a1b3e5f7d9c0a2b4e").unwrap();
+        let node_id = hex::decode(node_id_of(&keypair)).unwrap();
+        let mut clock = Clock::new();
+        clock.inc(node_id_of(&keypair));
+        let clock_info = ClockInfo::new(clock.clone(), clock.hash_hex(), node_id_of(&keypair), String::new(), 1);
+        let mut hello = NodeHello {
+            node_id: node_id.clone(),
+            public_key: node_id.clone(),
+            protocol_version: PROTOCOL_VERSION,
+            clock_info: Some(clockinfo_to_proto()(clock_info)),
+            to: vec![7; 32],
+            timestamp: 1000,
+            signature: Vec::new(),
+        };
+        hello.signature = sign_payload(&keypair, &hello_payload(&hello));
+        assert!(verify_payload(&node_id, &hello_payload(&hello), &hello.signature));
+
+        let mut tampered = hello.clone();
+        tampered.timestamp += 1;
+        assert!(!verify_payload(&node_id, &hello_payload(&tampered), &tampered.signature));
+
+        let mut tampered = hello.clone();
+        tampered.to = vec![8; 32];
+        assert!(!verify_payload(&node_id, &hello_payload(&tampered), &tampered.signature));
+
+        let mut tampered = hello;
+        clock.inc(node_id_of(&keypair));
+        tampered.clock_info.as_mut().unwrap().clock = Some((&clock).into());
+        assert!(!verify_payload(&node_id, &hello_payload(&tampered), &tampered.signature));
+    }
+}
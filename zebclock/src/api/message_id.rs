@@ -0,0 +1,46 @@
+//! Content-addressed message ids.
+//!
+//! The id of a `ZMessage` is the sha256 digest of the message encoded with
+//! empty `id` and `signature` fields, so equal contents share one id and other
+//! contents can't take it. A client sets the id before signing, the signature
+//! then covers it.
+
+use prost::Message;
+use protos::zmessage::ZMessage;
+use sha2::{Digest, Sha256};
+
+/// Id of a message derived from its contents.
+pub fn message_id(msg: &ZMessage) -> Vec<u8> {
+    let canonical = ZMessage { id: Vec::new(), signature: Vec::new(), ..msg.clone() };
+    Sha256::digest(canonical.encode_to_vec()).to_vec()
+}
+
+/// Return true if the id of a message is the one of its contents.
+pub fn verify_message_id(msg: &ZMessage) -> bool {
+    msg.id == message_id(msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protos::zmessage::ZType;
+
+    #[test]
+    fn content_addressed_ids() {
+        let mut msg = ZMessage { r#type: ZType::Zchat.into(), data: b"hello".to_vec(), ..Default::default() };
+        msg.id = message_id(&msg);
+        assert_eq!(msg.id.len(), 32);
+        assert!(verify_message_id(&msg));
+
+        // the signature is made after the id
+        msg.signature = vec![7; 64];
+        assert!(verify_message_id(&msg));
+
+        let mut other = msg.clone();
+        other.data = b"hellO".to_vec();
+        assert!(!verify_message_id(&other));
+        let mut renamed = msg.clone();
+        renamed.id[0] ^= 1;
+        assert!(!verify_message_id(&renamed));
+    }
+}
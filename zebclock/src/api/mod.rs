@@ -1,3 +1,8 @@
 pub mod read;
 pub mod write;
-pub mod response;
\ No newline at end of file
+pub mod response;
+pub mod sync;
+pub mod handshake;
+pub mod signature;
+pub mod endorsement;
+pub mod message_id;
\ No newline at end of file
@@ -8,10 +8,10 @@ use crate::zchronod::ZchronodArc;
 use tracing::*;
 use crate::api::response::{
     make_query_response, respond_cli_query,
-    clockinfo_to_proto, mergelog_to_proto
+    clockinfo_to_proto, mergelog_to_proto, peer_to_proto
 };
 use protos::bussiness::{
-    GatewayType, QueryByMsgId, QueryByTableKeyId, QueryMethod, QueryStatus, ZGateway
+    GatewayType, NodeInfo, QueryByMsgId, QueryByTableKeyId, QueryMethod, QueryStatus, StableFrontier, ZGateway
 };
 
 pub async fn handle_cli_read_msg(arc_zchronod: ZchronodArc, inner_msg: Innermsg, p2p_msg: &ZMessage, src: SocketAddr) {
@@ -30,6 +30,9 @@ pub async fn handle_cli_read_msg(arc_zchronod: ZchronodArc, inner_msg: Innermsg,
                         QueryMethod::QueryByMsgid => query_by_msgid(arc_zchronod, inner_msg, m, src).await,
                         QueryMethod::QueryByTableKeyid => query_by_table_keyid(arc_zchronod, inner_msg, m, src).await,
                         QueryMethod::QueryStatus => query_status(arc_zchronod, inner_msg, m, src).await,
+                        QueryMethod::QueryPending => query_pending(arc_zchronod, inner_msg, m, src).await,
+                        QueryMethod::QueryTotalOrder => query_total_order(arc_zchronod, inner_msg, m, src).await,
+                        QueryMethod::QueryStableFrontier => query_stable_frontier(arc_zchronod, inner_msg, m, src).await,
                     }
                 },
             }
@@ -53,6 +56,7 @@ async fn query_by_msgid(arc_zchronod: ZchronodArc, inner_msg: Innermsg, m: ZGate
             let (success, message, data) = match m.r#type() {
                 GatewayType::ClockNode => query_clock_by_msgid(&arc_zchronod, &query).await,
                 GatewayType::ZMessage => query_zmessage_by_msgid(&arc_zchronod, query).await,
+                GatewayType::NodeInfo => query_node_info_by_id(&arc_zchronod, &query).await,
                 _ => (false, "Not support gateway_type".to_string(), Vec::new()),
             };
 
@@ -83,6 +87,42 @@ async fn query_zmessage_by_msgid(arc_zchronod: &ZchronodArc, query: QueryByMsgId
     (success, message, data)
 }
 
+/// Registry entry of one node, the msg_id param is the node id.
+async fn query_node_info_by_id(arc_zchronod: &ZchronodArc, query: &QueryByMsgId) -> (bool, String, Vec<u8>) {
+    let node_id = query.msg_id.to_lowercase();
+    let peers = arc_zchronod.peers.read().await;
+    match peers.get(&node_id) {
+        None => (false, format!("Node {} not found", node_id), Vec::new()),
+        Some(peer) => {
+            let now = tools::helper::get_time_ms();
+            let info = NodeInfo {
+                node_ids: vec![node_id.clone()],
+                peers: vec![peer_to_proto(&node_id, peer, now, arc_zchronod.config.node.heartbeat_timeout)],
+            };
+            (true, String::new(), info.encode_to_vec())
+        }
+    }
+}
+
+/// Known peers sorted by node id, `last_pos` is the number already read.
+async fn query_node_info_batch(arc_zchronod: &ZchronodArc, query: QueryByTableKeyId, batch_num: u64) -> (bool, String, Vec<u8>) {
+    let now = tools::helper::get_time_ms();
+    let timeout = arc_zchronod.config.node.heartbeat_timeout;
+    let peers = arc_zchronod.peers.read().await;
+    let mut node_ids: Vec<&String> = peers.keys().collect();
+    node_ids.sort();
+    let peers: Vec<_> = node_ids.into_iter()
+        .skip(query.last_pos.try_into().unwrap_or(usize::MAX))
+        .take(batch_num.try_into().unwrap_or(usize::MAX))
+        .map(|node_id| peer_to_proto(node_id, &peers[node_id], now, timeout))
+        .collect();
+    let info = NodeInfo {
+        node_ids: peers.iter().map(|peer| peer.node_id.clone()).collect(),
+        peers,
+    };
+    (true, String::new(), info.encode_to_vec())
+}
+
 pub async fn query_by_table_keyid(arc_zchronod: ZchronodArc, inner_msg: Innermsg, m: ZGateway, src: SocketAddr) {
     info!(target: "Query API", "method = {:?}, type = {:?}, request_id = {}", m.method(), m.r#type(), m.request_id);
     let gateway_data = prost::bytes::Bytes::from(m.data.clone());
@@ -99,7 +139,7 @@ pub async fn query_by_table_keyid(arc_zchronod: ZchronodArc, inner_msg: Innermsg
                 GatewayType::ClockNode => query_clockinfo_batch(&arc_zchronod, query, batch_num).await,
                 GatewayType::MergeLog => query_mergelog_batch(&arc_zchronod, query, batch_num).await,
                 GatewayType::ZMessage => query_zmessage_batch(&arc_zchronod, query, batch_num).await,
-                _ => (false, "Not support gateway_type".to_string(), Vec::new()),
+                GatewayType::NodeInfo => query_node_info_batch(&arc_zchronod, query, batch_num).await,
             };
             let response = make_query_response(success, message, &data, m.request_id);
             respond_cli_query(arc_zchronod, inner_msg, &response.encode_to_vec(), src).await;
@@ -176,4 +216,53 @@ async fn query_status(arc_zchronod: ZchronodArc, inner_msg: Innermsg, m: ZGatewa
     
     let response = make_query_response(true, String::new(), &status.encode_to_vec(), m.request_id);
     respond_cli_query(arc_zchronod, inner_msg, &response.encode_to_vec(), src).await;
-}
\ No newline at end of file
+}
+
+/// Clocks of the event triggers stuck in the causal buffer, oldest sender index first.
+async fn query_pending(arc_zchronod: ZchronodArc, inner_msg: Innermsg, m: ZGateway, src: SocketAddr) {
+    info!(target: "Query API", "method = {:?}, type = {:?}, request_id = {}", m.method(), m.r#type(), m.request_id);
+    let clock_infos = arc_zchronod.state.read().await.pending
+        .values()
+        .map(|event| event.clock_info.clone())
+        .map(clockinfo_to_proto())
+        .collect::<Vec<_>>();
+
+    let response = make_query_response(true, String::new(), &ProtoClockInfos{clock_infos}.encode_to_vec(), m.request_id);
+    respond_cli_query(arc_zchronod, inner_msg, &response.encode_to_vec(), src).await;
+}
+
+/// Messages in the deterministic total order shared by all nodes, `last_pos` is the number already read.
+async fn query_total_order(arc_zchronod: ZchronodArc, inner_msg: Innermsg, m: ZGateway, src: SocketAddr) {
+    info!(target: "Query API", "method = {:?}, type = {:?}, request_id = {}", m.method(), m.r#type(), m.request_id);
+    let gateway_data = prost::bytes::Bytes::from(m.data.clone());
+    let (success, message, data) = match QueryByTableKeyId::decode(gateway_data) {
+        Err(err) => {
+            error!("QueryTotalOrder params format error, err={:?}", err);
+            (false, format!("Params format error: {:?}", err), Vec::new())
+        }
+        Ok(query) => {
+            let batch_num = arc_zchronod.config.api.read_maximum;
+            match arc_zchronod.storage.get_zmessages_in_total_order(query.last_pos, batch_num).await {
+                Ok(z_messages) => (true, String::new(), ZMessages{messages: z_messages}.encode_to_vec()),
+                Err(err) => (false, err.to_string(), Vec::new()),
+            }
+        }
+    };
+    let response = make_query_response(success, message, &data, m.request_id);
+    respond_cli_query(arc_zchronod, inner_msg, &response.encode_to_vec(), src).await;
+}
+
+/// Causally stable frontier over the matrix clock built from event triggers.
+async fn query_stable_frontier(arc_zchronod: ZchronodArc, inner_msg: Innermsg, m: ZGateway, src: SocketAddr) {
+    info!(target: "Query API", "method = {:?}, type = {:?}, request_id = {}", m.method(), m.r#type(), m.request_id);
+    let frontier = {
+        let state = arc_zchronod.state.read().await;
+        StableFrontier {
+            clock: Some((&state.stable_frontier()).into()),
+            node_ids: state.peer_clocks.keys().cloned().collect(),
+        }
+    };
+
+    let response = make_query_response(true, String::new(), &frontier.encode_to_vec(), m.request_id);
+    respond_cli_query(arc_zchronod, inner_msg, &response.encode_to_vec(), src).await;
+}
@@ -1,13 +1,18 @@
-use crate::zchronod::ZchronodArc;
+use super::endorsement::sign_srv_msg;
+use crate::zchronod::{PeerInfo, ZchronodArc};
+use p2p::Link;
 use prost::Message;
 use protos::{
-    bussiness::QueryResponse,
+    bussiness::{PeerInfo as ProtoPeerInfo, QueryResponse},
     innermsg::{Action, Identity, Innermsg, PushType},
+    vlc::ZClock,
+    zmessage::{ZMessage, ZType},
 };
 use crate::vlc::ClockInfo;
 use crate::vlc::MergeLog;
 use protos::vlc::Clock as ProtoClock;
 use protos::vlc::ClockInfo as ProtoClockInfo;
+use protos::vlc::HybridClock as ProtoHybridClock;
 use protos::vlc::MergeLog as ProtoMergeLog;
 use std::net::SocketAddr;
 use tracing::*;
@@ -26,15 +31,22 @@ pub fn clockinfo_to_proto() -> impl FnMut(ClockInfo) -> ProtoClockInfo {
         let node_id = hex::decode(clock_info.node_id).unwrap_or_else(|_| Vec::new());
         let clock_hash = hex::decode(clock_info.clock_hash).unwrap_or_else(|_| Vec::new());
         let msg_id = hex::decode(clock_info.message_id).unwrap_or_else(|_| Vec::new());
+        let prev_clock_hash = hex::decode(clock_info.prev_clock_hash).unwrap_or_else(|_| Vec::new());
+        let signature = hex::decode(clock_info.signature).unwrap_or_else(|_| Vec::new());
         ProtoClockInfo {
-            clock: Some(ProtoClock {
-                values: clock_info.clock.values.into_iter().map(|(k, v)| (k, v as u64)).collect(),
-            }),
+            clock: Some(ProtoClock::from(&clock_info.clock)),
             node_id,
             clock_hash,
             message_id: msg_id,
-            count: clock_info.count as u64,
-            create_at: clock_info.create_at as u64,
+            event_count: Some(clock_info.count.into()),
+            create_at: u64::try_from(clock_info.create_at).unwrap_or(u64::MAX),
+            prev_clock_hash,
+            signature,
+            hlc: Some(ProtoHybridClock {
+                physical: clock_info.hlc.physical,
+                logical: clock_info.hlc.logical,
+            }),
+            sign_version: clock_info.sign_version,
         }
     }
 }
@@ -49,15 +61,27 @@ pub fn mergelog_to_proto() -> impl FnMut(MergeLog) -> ProtoMergeLog {
         ProtoMergeLog {
             from_id,
             to_id,
-            start_count: merge_log.start_count as u64,
-            end_count: merge_log.end_count as u64,
+            start_event_count: Some(merge_log.start_count.into()),
+            end_event_count: Some(merge_log.end_count.into()),
             s_clock_hash,
             e_clock_hash,
-            merge_at: merge_log.merge_at as u64,
+            merge_at: u64::try_from(merge_log.merge_at).unwrap_or(u64::MAX),
         }
     }
 }
 
+/// Registry entry of a peer, alive if it sent a heartbeat within `timeout` milliseconds of `now`.
+pub fn peer_to_proto(node_id: &str, peer: &PeerInfo, now: u128, timeout: u64) -> ProtoPeerInfo {
+    ProtoPeerInfo {
+        node_id: node_id.to_owned(),
+        address: peer.address.to_string(),
+        public_key: node_id.to_owned(),
+        last_heartbeat: u64::try_from(peer.last_heartbeat).unwrap_or(u64::MAX),
+        last_clock: peer.last_clock.clone().map(clockinfo_to_proto()),
+        alive: peer.is_alive(now, timeout),
+    }
+}
+
 pub async fn broadcast_srv_state(arc_zchronod: ZchronodArc, mut inner: Innermsg, p2p_data: &[u8], src: SocketAddr) {
     let mut p2p_msg = inner.message.unwrap();
     p2p_msg.data = p2p_data.to_vec();
@@ -65,11 +89,65 @@ pub async fn broadcast_srv_state(arc_zchronod: ZchronodArc, mut inner: Innermsg,
     inner.identity = Identity::Server.into();
     inner.action = Action::WriteReply.into();
     inner.push_type = PushType::Broadcast.into();
+    sign_srv_msg(&arc_zchronod, &mut inner).await;
 
-    let mut buf = vec![];
-    inner.encode(&mut buf).unwrap();
     info!("Response Srv: {:?}", inner);
-    arc_zchronod.socket.send_to(&buf, src).await.unwrap_or(0);
+    if !arc_zchronod.network.has_outer() {
+        // the relay at src does the fan-out
+        if let Err(err) = arc_zchronod.network.send(Link::Inner, &inner, src).await {
+            error!("Response Srv to {} failed, err={}", src, err);
+        }
+        return;
+    }
+    let targets = arc_zchronod.direct_targets().await;
+    // a client write is answered on the inner link, peers get it directly
+    if !targets.contains(&src) {
+        if let Err(err) = arc_zchronod.network.send(Link::Inner, &inner, src).await {
+            error!("Response Srv to {} failed, err={}", src, err);
+        }
+    }
+    arc_zchronod.network.broadcast(&inner, &targets, Some(src)).await;
+}
+
+/// Send a clock sync message (DiffReq, DiffResp...) to one server node through the relay at src,
+/// on `link`: the one the message answered came in on, `Link::Outer` for our own initiative.
+pub async fn send_srv_clock(arc_zchronod: ZchronodArc, z_clock: ZClock, to: &str, link: Link, src: SocketAddr) {
+    let p2p_msg = ZMessage {
+        r#type: ZType::Clock.into(),
+        from: hex::decode(&arc_zchronod.node_id).unwrap_or_else(|_| Vec::new()),
+        to: hex::decode(to).unwrap_or_else(|_| Vec::new()),
+        data: z_clock.encode_to_vec(),
+        ..Default::default()
+    };
+    let mut inner = Innermsg {
+        identity: Identity::Server.into(),
+        action: Action::Write.into(),
+        push_type: PushType::Direct.into(),
+        message: Some(p2p_msg),
+        ..Default::default()
+    };
+    sign_srv_msg(&arc_zchronod, &mut inner).await;
+
+    info!("Send Srv: clock type = {:?}, to = {}", z_clock.r#type(), to);
+    if let Err(err) = arc_zchronod.network.send(link, &inner, src).await {
+        error!("Send Srv to {} failed, err={}", src, err);
+    }
+}
+
+/// Refuse a client write with a failed QueryResponse carrying the reason.
+pub(crate) async fn respond_cli_write_error(arc_zchronod: ZchronodArc, mut inner: Innermsg, reason: &str, src: SocketAddr) {
+    let mut p2p_msg = inner.message.unwrap_or_default();
+    let response = make_query_response(false, reason.to_owned(), &[], hex::encode(&p2p_msg.id));
+    p2p_msg.data = response.encode_to_vec();
+    inner.message = Some(p2p_msg);
+    inner.identity = Identity::Server.into();
+    inner.action = Action::WriteReply.into();
+    inner.push_type = PushType::Direct.into();
+
+    info!("Response Cli error: {}", reason);
+    if let Err(err) = arc_zchronod.network.send(Link::Inner, &inner, src).await {
+        error!("Response Cli to {} failed, err={}", src, err);
+    }
 }
 
 pub(crate) async fn respond_cli_query(arc_zchronod: ZchronodArc, mut inner: Innermsg, p2p_data: &[u8], src: SocketAddr) {
@@ -80,8 +158,8 @@ pub(crate) async fn respond_cli_query(arc_zchronod: ZchronodArc, mut inner: Inne
     inner.action = Action::ReadReply.into();
     inner.push_type = PushType::Direct.into();
 
-    let mut buf = vec![];
-    inner.encode(&mut buf).unwrap();
     info!("Response Cli: {:?}", inner);
-    arc_zchronod.socket.send_to(&buf, src).await.unwrap();
+    if let Err(err) = arc_zchronod.network.send(Link::Inner, &inner, src).await {
+        error!("Response Cli to {} failed, err={}", src, err);
+    }
 }
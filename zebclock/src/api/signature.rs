@@ -0,0 +1,92 @@
+//! Client message signatures.
+//!
+//! A client signs the `ZMessage` encoded with an empty `signature` field, with
+//! the key in `public_key` under the scheme in `signature_scheme`. Whether a
+//! write must carry a valid signature is configured per `ZType`.
+
+use std::fmt;
+use node_api::config::SignaturePolicy;
+use prost::Message;
+use protos::zmessage::{SignatureScheme, ZMessage};
+use tools::crypto::{sign_payload, verify_payload, NodeKeypair};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureError {
+    Missing,
+    UnsupportedScheme(SignatureScheme),
+    Invalid,
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureError::Missing => write!(f, "message signature required"),
+            SignatureError::UnsupportedScheme(scheme) => write!(f, "signature scheme {} not supported", scheme.as_str_name()),
+            SignatureError::Invalid => write!(f, "invalid message signature"),
+        }
+    }
+}
+
+/// Bytes a message signature is made over.
+pub fn signing_payload(msg: &ZMessage) -> Vec<u8> {
+    ZMessage { signature: Vec::new(), ..msg.clone() }.encode_to_vec()
+}
+
+/// Sign `msg` with a secp256k1 keypair, setting its public key and scheme.
+pub fn sign_zmessage(keypair: &NodeKeypair, msg: &mut ZMessage) {
+    msg.public_key = keypair.x_only_public_key().0.serialize().to_vec();
+    msg.signature_scheme = SignatureScheme::Secp256k1Schnorr.into();
+    msg.signature = sign_payload(keypair, &signing_payload(msg));
+}
+
+pub fn verify_zmessage(msg: &ZMessage) -> Result<(), SignatureError> {
+    if msg.signature.is_empty() || msg.public_key.is_empty() {
+        return Err(SignatureError::Missing);
+    }
+    match msg.signature_scheme() {
+        SignatureScheme::Secp256k1Schnorr => verify_payload(&msg.public_key, &signing_payload(msg), &msg.signature)
+            .then_some(())
+            .ok_or(SignatureError::Invalid),
+        scheme @ SignatureScheme::Ed25519 => Err(SignatureError::UnsupportedScheme(scheme)),
+    }
+}
+
+/// Check a client write against the signature policy of its type.
+pub fn check_signature(policy: SignaturePolicy, msg: &ZMessage) -> Result<(), SignatureError> {
+    match policy {
+        SignaturePolicy::Off => Ok(()),
+        SignaturePolicy::Optional if msg.signature.is_empty() => Ok(()),
+        SignaturePolicy::Optional | SignaturePolicy::Required => verify_zmessage(msg),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protos::zmessage::ZType;
+    use tools::crypto::keypair_from_hex;
+
+    const SECRET_KEY: &str = "b8a0a5c1e8d5f8a9bd8ea2bb12d5bb7e4d5a9a3f0c8d1f7e6b5a4c3d2e1f0a9b";
+
+    #[test]
+    fn client_signature_policies() {
+        let keypair = keypair_from_hex(SECRET_KEY).unwrap();
+        let unsigned = ZMessage { id: vec![1; 32], r#type: ZType::Zchat.into(), data: b"hello".to_vec(), ..Default::default() };
+        let mut signed = unsigned.clone();
+        sign_zmessage(&keypair, &mut signed);
+        assert_eq!(verify_zmessage(&signed), Ok(()));
+
+        let mut tampered = signed.clone();
+        tampered.data = b"hellO".to_vec();
+        assert_eq!(verify_zmessage(&tampered), Err(SignatureError::Invalid));
+        let mut ed25519 = signed.clone();
+        ed25519.signature_scheme = SignatureScheme::Ed25519.into();
+        assert_eq!(verify_zmessage(&ed25519), Err(SignatureError::UnsupportedScheme(SignatureScheme::Ed25519)));
+
+        assert_eq!(check_signature(SignaturePolicy::Off, &tampered), Ok(()));
+        assert_eq!(check_signature(SignaturePolicy::Optional, &unsigned), Ok(()));
+        assert_eq!(check_signature(SignaturePolicy::Optional, &tampered), Err(SignatureError::Invalid));
+        assert_eq!(check_signature(SignaturePolicy::Required, &unsigned), Err(SignatureError::Missing));
+        assert_eq!(check_signature(SignaturePolicy::Required, &signed), Ok(()));
+    }
+}
@@ -0,0 +1,373 @@
+//! Server to server clock sync.
+//!
+//! A node event is every message it applied, so a peer that knows our
+//! dimension up to `n` holds our first `n` events. Catch-up sends the
+//! events after that index together with the signed clock right after the
+//! last sent event, which covers exactly what the diffs hold.
+
+use std::net::SocketAddr;
+use prost::Message;
+use p2p::Link;
+use protos::vlc::{ActiveSync, ClockDigest, ClockInfo as ProtoClockInfo, ClockType, DiffReq, DiffResp, Heartbeat, ZClock};
+use std::cmp;
+use protos::zmessage::ZMessage;
+use crate::vlc::{ClockInfo, LogicalClock};
+use crate::zchronod::ZchronodArc;
+use tracing::*;
+
+use super::message_id::verify_message_id;
+use super::response::{clockinfo_to_proto, send_srv_clock};
+use super::write::persist_delivered;
+
+/// Ask node `to` for its events we are missing.
+pub async fn request_diff(arc_zchronod: ZchronodArc, to: &str, link: Link, src: SocketAddr) {
+    let from_clock = Some(arc_zchronod.state.read().await.clock_info.clone()).map(clockinfo_to_proto());
+    let req = DiffReq {
+        to: hex::decode(to).unwrap_or_else(|_| Vec::new()),
+        from_clock,
+    };
+    let z_clock = ZClock {
+        r#type: ClockType::DiffReq.into(),
+        data: req.encode_to_vec(),
+    };
+    send_srv_clock(arc_zchronod, z_clock, to, link, src).await;
+}
+
+/// Push our events the concurrent `peer` clock misses, the peer applies them
+/// and answers with its own missing events, so both sides converge.
+pub async fn active_sync(arc_zchronod: ZchronodArc, peer: &ClockInfo, link: Link, src: SocketAddr) {
+    let Some((diffs, origins, latest)) = events_for_peer(&arc_zchronod, peer).await else {
+        return;
+    };
+    let sync = ActiveSync {
+        to: hex::decode(&peer.node_id).unwrap_or_else(|_| Vec::new()),
+        diffs,
+        latest: Some(latest).map(clockinfo_to_proto()),
+        origins,
+    };
+    info!("ActiveSync: {} events to node {}", sync.diffs.len(), peer.node_id);
+    let z_clock = ZClock {
+        r#type: ClockType::ActiveSync.into(),
+        data: sync.encode_to_vec(),
+    };
+    send_srv_clock(arc_zchronod, z_clock, &peer.node_id, link, src).await;
+}
+
+/// Gossip our signed clock to node `to`.
+pub async fn send_digest(arc_zchronod: ZchronodArc, to: &str, link: Link, src: SocketAddr) {
+    let clock_info = Some(arc_zchronod.state.read().await.clock_info.clone()).map(clockinfo_to_proto());
+    let digest = ClockDigest {
+        to: hex::decode(to).unwrap_or_else(|_| Vec::new()),
+        clock_info,
+    };
+    let z_clock = ZClock {
+        r#type: ClockType::Digest.into(),
+        data: digest.encode_to_vec(),
+    };
+    send_srv_clock(arc_zchronod, z_clock, to, link, src).await;
+}
+
+pub async fn handle_srv_digest(arc_zchronod: ZchronodArc, z_clock: ZClock, link: Link, src: SocketAddr) {
+    let digest = match ClockDigest::decode(prost::bytes::Bytes::from(z_clock.data)) {
+        Err(err) => {
+            error!("Decode ClockDigest error, err={:?}", err);
+            return;
+        }
+        Ok(digest) => digest,
+    };
+    if hex::encode(&digest.to) != arc_zchronod.node_id {
+        info!("ClockDigest is not for this node, skip");
+        return;
+    }
+    let Some(peer) = accept_clock(&arc_zchronod, digest.clock_info.as_ref(), "ClockDigest", src).await else {
+        return;
+    };
+
+    let ordering = arc_zchronod.state.read().await.compare(&peer.clock);
+    match ordering {
+        Some(cmp::Ordering::Equal) => {}
+        // the peer is behind, answer with our digest so it pulls the diff
+        Some(cmp::Ordering::Greater) => send_digest(arc_zchronod, &peer.node_id, link, src).await,
+        Some(cmp::Ordering::Less) => request_diff(arc_zchronod, &peer.node_id, link, src).await,
+        None => active_sync(arc_zchronod, &peer, link, src).await,
+    }
+}
+
+/// Tell node `to` that we are alive, with our signed clock.
+pub async fn send_heartbeat(arc_zchronod: ZchronodArc, to: &str, link: Link, src: SocketAddr) {
+    let clock_info = Some(arc_zchronod.state.read().await.clock_info.clone()).map(clockinfo_to_proto());
+    let heartbeat = Heartbeat {
+        to: hex::decode(to).unwrap_or_else(|_| Vec::new()),
+        clock_info,
+        trigger_clock_hash: arc_zchronod.last_trigger_hash(to).await,
+    };
+    let z_clock = ZClock {
+        r#type: ClockType::Heartbeat.into(),
+        data: heartbeat.encode_to_vec(),
+    };
+    send_srv_clock(arc_zchronod, z_clock, to, link, src).await;
+}
+
+pub async fn handle_srv_heartbeat(arc_zchronod: ZchronodArc, z_clock: ZClock, src: SocketAddr) {
+    let heartbeat = match Heartbeat::decode(prost::bytes::Bytes::from(z_clock.data)) {
+        Err(err) => {
+            error!("Decode Heartbeat error, err={:?}", err);
+            return;
+        }
+        Ok(heartbeat) => heartbeat,
+    };
+    if hex::encode(&heartbeat.to) != arc_zchronod.node_id {
+        info!("Heartbeat is not for this node, skip");
+        return;
+    }
+    if let Some(peer) = accept_clock(&arc_zchronod, heartbeat.clock_info.as_ref(), "Heartbeat", src).await {
+        arc_zchronod.note_heartbeat(&peer, src).await;
+        arc_zchronod.note_delta_ack(&peer.node_id, &heartbeat.trigger_clock_hash).await;
+    }
+}
+
+pub async fn handle_srv_diff_req(arc_zchronod: ZchronodArc, z_clock: ZClock, link: Link, src: SocketAddr) {
+    let req = match DiffReq::decode(prost::bytes::Bytes::from(z_clock.data)) {
+        Err(err) => {
+            error!("Decode DiffReq error, err={:?}", err);
+            return;
+        }
+        Ok(req) => req,
+    };
+    if hex::encode(&req.to) != arc_zchronod.node_id {
+        info!("DiffReq is not for this node, skip");
+        return;
+    }
+    let Some(from_clock) = accept_clock(&arc_zchronod, req.from_clock.as_ref(), "DiffReq", src).await else {
+        return;
+    };
+
+    let start_index = start_index_for(&arc_zchronod, &from_clock).await;
+    let Some((diffs, origins, latest)) = events_for_peer(&arc_zchronod, &from_clock).await else {
+        return;
+    };
+    let resp = DiffResp {
+        to: hex::decode(&from_clock.node_id).unwrap_or_else(|_| Vec::new()),
+        start_event_index: Some(start_index.into()),
+        diffs,
+        from_clock: Some(latest).map(clockinfo_to_proto()),
+        origins,
+    };
+    info!("DiffResp: {} events after {} to node {}", resp.diffs.len(), start_index, from_clock.node_id);
+    let z_clock = ZClock {
+        r#type: ClockType::DiffRsp.into(),
+        data: resp.encode_to_vec(),
+    };
+    send_srv_clock(arc_zchronod, z_clock, &from_clock.node_id, link, src).await;
+}
+
+pub async fn handle_srv_diff_resp(arc_zchronod: ZchronodArc, z_clock: ZClock, link: Link, src: SocketAddr) {
+    let resp = match DiffResp::decode(prost::bytes::Bytes::from(z_clock.data)) {
+        Err(err) => {
+            error!("Decode DiffResp error, err={:?}", err);
+            return;
+        }
+        Ok(resp) => resp,
+    };
+    if hex::encode(&resp.to) != arc_zchronod.node_id {
+        info!("DiffResp is not for this node, skip");
+        return;
+    }
+    let Some(from_clock) = accept_clock(&arc_zchronod, resp.from_clock.as_ref(), "DiffResp", src).await else {
+        return;
+    };
+
+    let progress = apply_events(&arc_zchronod, &resp.diffs, &resp.origins, &from_clock).await;
+
+    // a full batch means the sender may have more events, unless it taught us nothing
+    if progress && resp.diffs.len() as u64 >= arc_zchronod.config.node.sync_batch_maximum {
+        request_diff(arc_zchronod, &from_clock.node_id, link, src).await;
+    }
+}
+
+pub async fn handle_srv_active_sync(arc_zchronod: ZchronodArc, z_clock: ZClock, link: Link, src: SocketAddr) {
+    let sync = match ActiveSync::decode(prost::bytes::Bytes::from(z_clock.data)) {
+        Err(err) => {
+            error!("Decode ActiveSync error, err={:?}", err);
+            return;
+        }
+        Ok(sync) => sync,
+    };
+    if hex::encode(&sync.to) != arc_zchronod.node_id {
+        info!("ActiveSync is not for this node, skip");
+        return;
+    }
+    let Some(latest) = accept_clock(&arc_zchronod, sync.latest.as_ref(), "ActiveSync", src).await else {
+        return;
+    };
+
+    let progress = apply_events(&arc_zchronod, &sync.diffs, &sync.origins, &latest).await;
+
+    if progress && sync.diffs.len() as u64 >= arc_zchronod.config.node.sync_batch_maximum {
+        request_diff(arc_zchronod.clone(), &latest.node_id, link, src).await;
+    }
+
+    // send back our events the peer misses, nothing left means both sides converged
+    let own_count = arc_zchronod.state.read().await.clock_info.clock.get(&arc_zchronod.node_id);
+    if start_index_for(&arc_zchronod, &latest).await < own_count {
+        active_sync(arc_zchronod, &latest, link, src).await;
+    }
+}
+
+/// Decode and verify the signed clock of a sync message, check its drift,
+/// and remember its node as a peer.
+async fn accept_clock(arc_zchronod: &ZchronodArc, proto_clock: Option<&ProtoClockInfo>, kind: &str, src: SocketAddr) -> Option<ClockInfo> {
+    let Some(clock_info) = proto_clock.map(ClockInfo::from) else {
+        error!("{} without clock info, skip", kind);
+        return None;
+    };
+    if !clock_info.verify_signature() {
+        warn!("Reject {}: invalid clock signature from node {}", kind, clock_info.node_id);
+        return None;
+    }
+    if !arc_zchronod.within_drift(&clock_info) {
+        return None;
+    }
+    arc_zchronod.note_peer(&clock_info, src).await;
+    Some(clock_info)
+}
+
+/// Index of our last event the peer holds, from the common base of both clocks.
+async fn start_index_for(arc_zchronod: &ZchronodArc, peer: &ClockInfo) -> u128 {
+    let base = arc_zchronod.state.read().await.clock_info.clock.base_common(&peer.clock);
+    base.get(&arc_zchronod.node_id)
+}
+
+/// Our events after the common base with `peer` (everything for a genesis
+/// base), bounded by the sync batch size, their origin clocks, and the
+/// signed clock covering them.
+async fn events_for_peer(arc_zchronod: &ZchronodArc, peer: &ClockInfo) -> Option<(Vec<ZMessage>, Vec<ProtoClockInfo>, ClockInfo)> {
+    let start_index = start_index_for(arc_zchronod, peer).await;
+    let batch_num = arc_zchronod.config.node.sync_batch_maximum;
+    let events = arc_zchronod.storage.get_events_since(&arc_zchronod.node_id, start_index, batch_num).await.ok()?;
+    let latest = match events.last() {
+        Some((clock_info, _, _)) => clock_info.clone(),
+        None => arc_zchronod.state.read().await.clock_info.clone(),
+    };
+    let (diffs, origins) = events.into_iter().map(|(_, msg, origin)| (msg, clockinfo_to_proto()(origin))).unzip();
+    Some((diffs, origins, latest))
+}
+
+/// Apply diffs in the event order of the sender, which is a causal order,
+/// then merge the sender clock that covers them and record the merge.
+/// Diffs without origin clock (older senders) take the sender clock as origin.
+/// A diff whose message id isn't the hash of its contents rejects the whole
+/// batch, since the merge must not cover events we didn't store.
+/// Returns true if we now know more events of the sender than before.
+async fn apply_events(arc_zchronod: &ZchronodArc, diffs: &[ZMessage], origins: &[ProtoClockInfo], from_clock: &ClockInfo) -> bool {
+    let origins: Vec<ClockInfo> = origins.iter().map(ClockInfo::from).collect();
+    if let Some(origin) = origins.iter().find(|origin| !origin.verify_signature()) {
+        warn!("Reject diffs: invalid origin clock signature from node {}", origin.node_id);
+        return false;
+    }
+    if let Some(msg) = diffs.iter().find(|msg| !verify_message_id(msg)) {
+        warn!("Reject diffs: message id {} from node {} is not the hash of its contents", hex::encode(&msg.id), from_clock.node_id);
+        return false;
+    }
+    let known_before = arc_zchronod.state.read().await.clock_info.clock.get(&from_clock.node_id);
+    let storage = &arc_zchronod.storage;
+    for (i, msg) in diffs.iter().enumerate() {
+        if storage.has_zmessage(&hex::encode(&msg.id)).await {
+            continue;
+        }
+        let added = {
+            let mut state = arc_zchronod.state.write().await;
+            state.add(vec![msg.clone()]).then(|| state.clock_info.clone())
+        };
+        if let Some(clock_info) = added {
+            storage.sinker_clock(clock_info.message_id.clone(), vec![], &clock_info).await;
+            storage.sinker_zmessage(msg.clone(), origins.get(i).unwrap_or(from_clock)).await;
+        }
+    }
+
+    let merged = {
+        let mut state = arc_zchronod.state.write().await;
+        let (_, merged) = state.merge(from_clock.clone(), &[]);
+        merged.then(|| state.clock_info.clone())
+    };
+    if let Some(clock_info) = merged {
+        storage.sinker_clock(clock_info.message_id.clone(), vec![], &clock_info).await;
+        storage.sinker_merge_log(from_clock, &clock_info).await;
+    }
+
+    // the catch-up may unblock triggers waiting in the causal buffer
+    let delivered = arc_zchronod.state.write().await.deliver_pending();
+    persist_delivered(arc_zchronod, delivered).await;
+    arc_zchronod.state.read().await.clock_info.clock.get(&from_clock.node_id) > known_before
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{HashMap, VecDeque};
+    use std::sync::Arc;
+    use node_api::config::ZchronodConfig;
+    use p2p::{Network, ReconnectPolicy, UdpTransport};
+    use sea_orm::{DatabaseBackend, MockDatabase};
+    use tokio::sync::{Mutex, RwLock};
+    use tools::crypto::{keypair_from_hex, node_id_of};
+    use crate::api::message_id::message_id;
+    use crate::storage::Storage;
+    use crate::zchronod::{ServerState, Zchronod};
+
+    fn new_state(secret_key: &str) -> ServerState {
+        let keypair = keypair_from_hex(secret_key).unwrap();
+        ServerState::new(node_id_of(&keypair), keypair, 100)
+    }
+
+    fn message(data: &[u8]) -> ZMessage {
+        let mut msg = ZMessage { data: data.to_vec(), ..Default::default() };
+        msg.id = message_id(&msg);
+        msg
+    }
+
+    /// A node whose database answers every statement with an error.
+    async fn new_zchronod(state: ServerState) -> ZchronodArc {
+        let mut config = ZchronodConfig::default();
+        config.node.max_clock_drift = 60_000;
+        config.node.sync_batch_maximum = 100;
+        let inner = Box::new(UdpTransport::bind("127.0.0.1:0").await.unwrap());
+        Arc::new(Zchronod {
+            config: Arc::new(config),
+            node_id: state.clock_info.node_id.clone(),
+            network: Network::new(inner, None, ReconnectPolicy::default()),
+            storage: Storage { pg_db: Arc::new(MockDatabase::new(DatabaseBackend::Postgres).into_connection()) },
+            state: RwLock::new(state),
+            peers: RwLock::new(HashMap::new()),
+            broadcast_clocks: Mutex::new(VecDeque::new()),
+            trigger_clocks: RwLock::new(HashMap::new()),
+            hello_times: Mutex::new(HashMap::new()),
+        })
+    }
+
+    #[tokio::test]
+    async fn tampered_diff_leaves_clock() {
+        let mut sender = new_state("5b3e8f2d1c7a4b6e9f0d2c8a7b5e3f1d4c6a8b0e2f4d6c8a1b3e5f7d9c0a2b4e");
+        let first = message(b"first");
+        let mut second = message(b"second");
+        sender.add(vec![first.clone()]);
+        sender.add(vec![second.clone()]);
+        second.data = b"forged".to_vec();
+
+        let zchronod = new_zchronod(new_state("e1a7c9d3b5f2a8c4e6d0b9f7a3c5e1d8b2f4a6c0e9d7b3f5a1c8e2d4b6f0a9c3")).await;
+        let before = zchronod.state.read().await.clock_info.clone();
+        let resp = DiffResp {
+            to: hex::decode(&zchronod.node_id).unwrap(),
+            from_clock: Some(clockinfo_to_proto()(sender.clock_info.clone())),
+            diffs: vec![first, second],
+            ..Default::default()
+        };
+        let z_clock = ZClock { r#type: ClockType::DiffRsp.into(), data: resp.encode_to_vec() };
+        handle_srv_diff_resp(zchronod.clone(), z_clock, Link::Inner, "127.0.0.1:8050".parse().unwrap()).await;
+
+        let after = zchronod.state.read().await.clock_info.clone();
+        assert_eq!(after.clock, before.clock);
+        assert_eq!(after.clock_hash, before.clock_hash);
+        assert_eq!(after.clock.get(&sender.clock_info.node_id), 0);
+    }
+}
@@ -1,27 +1,46 @@
 use std::net::SocketAddr;
+use p2p::Link;
 use crate::vlc::ClockInfo;
 use protos::innermsg::Innermsg;
 use protos::vlc::{ClockType, EventTrigger, ZClock};
 use protos::zmessage::{ZMessage, ZType};
 use protos::bussiness::ZChat;
 use prost::Message;
-use crate::zchronod::ZchronodArc;
+use crate::zchronod::{DeliveredEvent, PendingEvent, ZchronodArc};
+use std::cmp;
 use tracing::*;
 
-use super::response::{broadcast_srv_state, clockinfo_to_proto};
+use super::response::{broadcast_srv_state, clockinfo_to_proto, respond_cli_write_error};
+use super::message_id::verify_message_id;
+use super::signature::check_signature;
+use super::sync::{active_sync, request_diff};
 
 pub async fn handle_cli_write_msg(arc_zchronod: ZchronodArc,mut inner_msg: Innermsg, p2p_msg: &ZMessage, src: SocketAddr) {
+    let policy = arc_zchronod.config.api.signatures.policy_for(p2p_msg.r#type());
+    if let Err(err) = check_signature(policy, p2p_msg) {
+        warn!("Reject client write {}: {}", hex::encode(&p2p_msg.id), err);
+        respond_cli_write_error(arc_zchronod, inner_msg, &err.to_string(), src).await;
+        return;
+    }
+    if !verify_message_id(p2p_msg) {
+        warn!("Reject client write {}: id is not the hash of its contents", hex::encode(&p2p_msg.id));
+        respond_cli_write_error(arc_zchronod, inner_msg, "message id is not the hash of its contents", src).await;
+        return;
+    }
     match p2p_msg.r#type() {
         ZType::Zchat =>{
             let zchat_msg = prost::bytes::Bytes::from(p2p_msg.data.clone());
             let m = ZChat::decode(zchat_msg).unwrap();
-            let write_success = arc_zchronod.state.write().await.add(vec![p2p_msg.clone()]); 
-            if write_success {
-                let update_clock_info: ClockInfo = arc_zchronod.state.read().await.clock_info.clone();
+            let write_success = {
+                let mut state = arc_zchronod.state.write().await;
+                state.add(vec![p2p_msg.clone()]).then(|| state.clock_info.clone())
+            };
+            if let Some(update_clock_info) = write_success {
                 let state_storage = &arc_zchronod.clone().storage;
                 state_storage.sinker_clock(hex::encode(p2p_msg.id.clone()),m.message_data, &update_clock_info).await;
-                state_storage.sinker_zmessage(p2p_msg.clone()).await;
-                let z_clock = make_event_trigger_zclock(arc_zchronod.clone(), p2p_msg).await;
+                state_storage.sinker_zmessage(p2p_msg.clone(), &update_clock_info).await;
+                // this node is the origin of the message
+                let z_clock = make_event_trigger_zclock(&arc_zchronod, update_clock_info.clone(), p2p_msg, update_clock_info).await;
                 let mut z_msg = inner_msg.message.unwrap();
                 z_msg.r#type = ZType::Clock.into();
                 inner_msg.message = Some(z_msg);
@@ -32,31 +51,136 @@ pub async fn handle_cli_write_msg(arc_zchronod: ZchronodArc,mut inner_msg: Inner
     }
 }
 
-pub async fn handle_srv_event_trigger(arc_zchronod: ZchronodArc, z_clock: ZClock, inner_msg: Innermsg, p2p_msg: &ZMessage,src: SocketAddr) {
+pub async fn handle_srv_event_trigger(arc_zchronod: ZchronodArc, z_clock: ZClock, inner_msg: Innermsg, link: Link, src: SocketAddr) {
     let event_msg = prost::bytes::Bytes::from(z_clock.data.clone());
     let event = EventTrigger::decode(event_msg).unwrap();
-    let prost_clock = event.clock_info.unwrap();
+    let mut prost_clock = event.clock_info.unwrap();
+    if let Some(delta) = event.clock_delta.as_ref() {
+        let node_id = hex::encode(&prost_clock.node_id);
+        match arc_zchronod.resolve_delta(&node_id, delta).await {
+            Some(clock) => prost_clock.clock = Some((&clock).into()),
+            None => {
+                // we don't hold the delta base, the diff carries the full clock and the event
+                info!("clock delta base mismatch with node {}, request diff", node_id);
+                request_diff(arc_zchronod, &node_id, link, src).await;
+                return;
+            }
+        }
+    }
     let input_clock_info :ClockInfo = (&prost_clock).into();
-    let (need_broadcast, merged) = arc_zchronod.state.write().await.merge(input_clock_info.clone(), &vec!(event.message.clone().unwrap()));
-    if need_broadcast {
-        let new_z_clock = make_event_trigger_zclock(arc_zchronod.clone(), &event.message.unwrap()).await;
-        broadcast_srv_state(arc_zchronod.clone(), inner_msg, &new_z_clock.encode_to_vec(), src).await;
-    } else {
-        info!("clock is bigger or equal, no actions");
+    if !input_clock_info.verify_signature() {
+        warn!("Reject event trigger: invalid clock signature from node {}", input_clock_info.node_id);
+        return;
+    }
+    if !arc_zchronod.within_drift(&input_clock_info) {
+        return;
     }
-    if merged {
-        let state_clock_info = &arc_zchronod.state.read().await.clock_info.clone();
-        arc_zchronod.storage.sinker_clock(state_clock_info.message_id.clone(), vec![], state_clock_info).await;
-        arc_zchronod.storage.sinker_merge_log(&input_clock_info, state_clock_info).await;
-        arc_zchronod.storage.sinker_zmessage(p2p_msg.clone()).await;
+    arc_zchronod.note_peer(&input_clock_info, src).await;
+    arc_zchronod.note_trigger_clock(&input_clock_info).await;
+    arc_zchronod.state.write().await.observe_peer(&input_clock_info);
+    let Some(message) = event.message else {
+        error!("Event trigger without message, skip");
+        return;
+    };
+    if !verify_message_id(&message) {
+        warn!("Reject event trigger from node {}: message id {} is not the hash of its contents", input_clock_info.node_id, hex::encode(&message.id));
+        return;
+    }
+    // triggers without origin come from nodes predating origin clocks, the sender stands in
+    let origin = match event.origin_clock.as_ref().map(ClockInfo::from) {
+        Some(origin) if !origin.verify_signature() => {
+            warn!("Reject event trigger: invalid origin clock signature from node {}", origin.node_id);
+            return;
+        }
+        Some(origin) => origin,
+        None => input_clock_info.clone(),
+    };
+
+    let event = PendingEvent {
+        clock_info: input_clock_info.clone(),
+        message,
+        origin,
+        inner_msg,
+        link,
+        src,
+        received_at: tools::helper::get_time_ms(),
+    };
+    let (ordering, delivered) = {
+        let mut state = arc_zchronod.state.write().await;
+        let ordering = state.compare(&input_clock_info.clock);
+        match ordering {
+            Some(cmp::Ordering::Equal) | Some(cmp::Ordering::Greater) => {
+                info!("clock is bigger or equal, no actions");
+                return;
+            }
+            _ if state.is_deliverable(&input_clock_info) => {
+                let mut delivered: Vec<DeliveredEvent> = state.deliver(event).into_iter().collect();
+                delivered.extend(state.deliver_pending());
+                (ordering, Some(delivered))
+            }
+            _ => {
+                if !state.buffer(event) {
+                    warn!("causal buffer is full, drop event trigger from node {}", input_clock_info.node_id);
+                }
+                (ordering, None)
+            }
+        }
+    };
+
+    // the trigger depends on events we don't hold, fetch them while it waits in the buffer
+    let Some(delivered) = delivered else {
+        if ordering.is_none() {
+            info!("concurrent clock with node {}, start active sync", input_clock_info.node_id);
+            active_sync(arc_zchronod, &input_clock_info, link, src).await;
+        } else {
+            info!("missing events before event trigger, request diff from node {}", input_clock_info.node_id);
+            request_diff(arc_zchronod, &input_clock_info.node_id, link, src).await;
+        }
+        return;
+    };
+    persist_delivered(&arc_zchronod, delivered).await;
+}
+
+/// Persist triggers applied to the state in delivery order, and rebroadcast
+/// the ones that carried a new message.
+pub(crate) async fn persist_delivered(arc_zchronod: &ZchronodArc, delivered: Vec<DeliveredEvent>) {
+    for DeliveredEvent { event, added, clock_info } in delivered {
+        arc_zchronod.storage.sinker_clock(clock_info.message_id.clone(), vec![], &clock_info).await;
+        arc_zchronod.storage.sinker_merge_log(&event.clock_info, &clock_info).await;
+        if added {
+            arc_zchronod.storage.sinker_zmessage(event.message.clone(), &event.origin).await;
+            let new_z_clock = make_event_trigger_zclock(arc_zchronod, clock_info, &event.message, event.origin).await;
+            broadcast_srv_state(arc_zchronod.clone(), event.inner_msg, &new_z_clock.encode_to_vec(), event.src).await;
+        }
     }
 }
 
-async fn make_event_trigger_zclock(arc_zchronod: ZchronodArc, inner_p2p_msg: &ZMessage) -> ZClock {
-    let proto_clock = Some(arc_zchronod.state.read().await.clock_info.clone()).map(clockinfo_to_proto());
+/// Drop the expired triggers of the causal buffer and request diffs from their senders.
+pub(crate) async fn expire_pending(arc_zchronod: ZchronodArc) {
+    let expired = arc_zchronod.state.write().await.expire_pending(tools::helper::get_time_ms());
+    let mut senders = std::collections::BTreeMap::new();
+    for event in expired {
+        warn!("event trigger from node {} timed out in causal buffer", event.clock_info.node_id);
+        senders.insert(event.clock_info.node_id, (event.link, event.src));
+    }
+    for (node_id, (link, src)) in senders {
+        request_diff(arc_zchronod.clone(), &node_id, link, src).await;
+    }
+}
+
+/// Event trigger for a broadcast, the clock goes as delta against a clock
+/// the live peers acked when that is smaller.
+async fn make_event_trigger_zclock(arc_zchronod: &ZchronodArc, clock_info: ClockInfo, inner_p2p_msg: &ZMessage, origin: ClockInfo) -> ZClock {
+    let clock_delta = arc_zchronod.broadcast_delta(&clock_info.clock).await;
+    let mut proto_clock = clockinfo_to_proto()(clock_info);
+    if clock_delta.is_some() {
+        proto_clock.clock = None;
+    }
     let event = EventTrigger {
-        clock_info: proto_clock,
-        message: Some(inner_p2p_msg.clone())
+        clock_info: Some(proto_clock),
+        message: Some(inner_p2p_msg.clone()),
+        origin_clock: Some(origin).map(clockinfo_to_proto()),
+        clock_delta,
     };
     
     ZClock {
@@ -64,34 +188,3 @@ async fn make_event_trigger_zclock(arc_zchronod: ZchronodArc, inner_p2p_msg: &ZM
         data: event.encode_to_vec(),
     }
 }
-
-// fn handle_event_trigger(&mut self, msg: EventTrigger) -> Option<ServerMessage> {
-//     match self.clock_info.clock.partial_cmp(&msg.clock_info.clock) {
-//         Some(cmp::Ordering::Equal) => None,
-//         Some(cmp::Ordering::Greater) => None,
-//         Some(cmp::Ordering::Less) => {
-//             let self_clock_info = ClockInfo::new(self.clock_info.clock.clone(), self.id, self.clock_info.message_id.clone(), 0);
-//             let req = ServerMessage::DiffReq(DiffReq { from_clock: self_clock_info, to:  msg.clock_info.id });
-//             Some(req)
-//         }
-//         None => {
-//             let base = self.clock_info.clock.base_common(&msg.clock_info.clock);
-//             if base.is_genesis() {
-//                 let req = ServerMessage::ActiveSync(ActiveSync { diffs: self.items.clone(), latest: self.clock_info.clone(), to: msg.clock_info.id });
-//                 Some(req)
-//             } else {
-//                 if let Some(start_msg_id) = self.clock_to_msgid.get(&base.index_key()) {
-//                     if let Some(diff_msg_ids) = get_suffix(&self.items, start_msg_id.to_string()) {
-//                         // req diff and send self diff
-//                         let req = ServerMessage::ActiveSync(ActiveSync { diffs: diff_msg_ids, latest: self.clock_info.clone(), to: msg.clock_info.id });
-//                         Some(req)
-//                     } else {
-//                         None
-//                     }
-//                 } else {
-//                     None
-//                 }
-//             }
-//         }
-//     }
-// }
@@ -5,11 +5,14 @@ use protos::{
     vlc::{Clock, ClockInfo, ClockType, EventTrigger, ZClock},
     zmessage::{ZMessage, ZType},
 };
+use zebclock::api::{message_id::message_id, signature::sign_zmessage};
 use std::{
     collections::HashMap,
     net::UdpSocket, thread,
 };
 
+const CLIENT_SECRET_KEY: &str = "c3d2e1f0a9b8a0a5c1e8d5f8a9bd8ea2bb12d5bb7e4d5a9a3f0c8d1f7e6b5a4d";
+
 fn main() -> std::io::Result<()> {
     let socket = UdpSocket::bind("127.0.0.1:0").expect("couldn't bind to address");
     let write_count = 2;
@@ -78,14 +81,17 @@ fn client_message() -> Vec<u8> {
     zchat.encode(&mut buf2).unwrap();
     println!("buf2: {:?}", buf2);
 
-    let p2p_msg = ZMessage {
-        id: hex::decode("78f16ee259fe7a23059890d81304476a75e6ba9df74b624226553c7fd545bcb2").unwrap(),
+    let mut p2p_msg = ZMessage {
         from: Vec::from("msgfrom"),
         to: Vec::from("msg.to"),
         r#type: ZType::Zchat.into(),
         data: buf2,
         ..Default::default()
     };
+    // passes a "required" signature policy
+    let keypair = tools::crypto::keypair_from_hex(CLIENT_SECRET_KEY).unwrap();
+    p2p_msg.id = message_id(&p2p_msg);
+    sign_zmessage(&keypair, &mut p2p_msg);
 
     let inner_msg = Innermsg {
         identity: Identity::Client.into(),
@@ -110,18 +116,20 @@ fn srv_event_trigger_message() -> Vec<u8> {
     };
 
     // empty data zmessage
-    let inner_state_zmsg = ZMessage {
-        id: Vec::from("intobytes"),
+    let mut inner_state_zmsg = ZMessage {
         from: Vec::from("msgfrom"),
         to: Vec::from("msg.to"),
         r#type: ZType::Zchat.into(),
         data: zchat.encode_to_vec(),
         ..Default::default()
     };
+    inner_state_zmsg.id = message_id(&inner_state_zmsg);
 
     let event = EventTrigger {
         clock_info: Some(clock_info),
         message: Some(inner_state_zmsg.clone()),
+        origin_clock: None,
+        clock_delta: None,
     };
     let z_clock = ZClock {
         r#type: ClockType::EventTrigger.into(),
@@ -152,12 +160,11 @@ fn srv_event_trigger_message() -> Vec<u8> {
 
 fn make_clock_info() -> ClockInfo {
     let mut values = HashMap::new();
-    values.insert("one".to_owned(), 1);
+    values.insert("one".to_owned(), 1u128.into());
 
-    let clock = Some(Clock { values });
+    let clock = Some(Clock { counters: values, ..Default::default() });
     let id = Vec::from("one");
     let message_id = Vec::from("message_id");
-    let count = 0;
     let create_at = tools::helper::get_time_ms();
 
     
@@ -166,7 +173,11 @@ fn make_clock_info() -> ClockInfo {
         node_id: id,
         clock_hash: Vec::new(),
         message_id,
-        count,
+        event_count: None,
         create_at: create_at.try_into().unwrap(),
+        prev_clock_hash: Vec::new(),
+        signature: Vec::new(),
+        hlc: None,
+        sign_version: 0,
     }
 }
\ No newline at end of file
@@ -1,11 +1,13 @@
 use crate::{
-    api::{read, write}, 
+    api::{endorsement, handshake, read, sync, write}, 
     zchronod::ZchronodArc,
 };
 use std::{
-    net::SocketAddr, sync::Arc,
+    collections::HashSet, net::SocketAddr, sync::Arc, time::Duration,
     // sync::atomic::{AtomicUsize, Ordering}, 
 };
+use rand::seq::SliceRandom;
+use p2p::Link;
 use protos::{vlc::{ClockType, ZClock}, zmessage::{ZMessage, ZType}};
 use websocket::ReceiveMessage;
 use prost::Message;
@@ -15,21 +17,109 @@ use tracing::*;
 // static MESSAGE_COUNT: AtomicUsize = AtomicUsize::new(0);
 
 pub(crate) async fn p2p_event_loop(arc_zchronod: ZchronodArc) {
-    info!("Now p2p udp listen on : {}", arc_zchronod.config.net.inner_p2p);
+    info!("Now p2p {:?} listen on : {}", arc_zchronod.config.net.inner_transport, arc_zchronod.config.net.inner_p2p);
+    if arc_zchronod.network.has_outer() {
+        info!("Now direct udp listen on : {:?}", arc_zchronod.config.net.outer_p2p);
+    }
+    loop {
+        match arc_zchronod.network.recv().await {
+            Ok((link, m, src)) => {
+                info!("Received: message from identity: {:?}, action: {:?}", m.identity(), m.action());
+                handle_msg(arc_zchronod.clone(), m, link, src).await;
+            }
+            Err(err) => warn!("P2p receive failed, err={}", err),
+        }
+    }
+}
+
+/// Periodically send our clock digest to a random subset of known peers, so a
+/// lost broadcast packet can't leave nodes diverged forever.
+pub(crate) async fn gossip_loop(arc_zchronod: ZchronodArc) {
+    let gossip_interval = arc_zchronod.config.node.gossip_interval;
+    if gossip_interval == 0 {
+        info!("Gossip is disabled");
+        return;
+    }
+    let mut interval = tokio::time::interval(Duration::from_millis(gossip_interval));
+    loop {
+        interval.tick().await;
+        let peers: Vec<(String, SocketAddr)> = {
+            let known_peers: Vec<(String, SocketAddr)> = arc_zchronod.peers.read().await.iter().map(|(id, peer)| (id.clone(), peer.address)).collect();
+            known_peers.choose_multiple(&mut rand::thread_rng(), arc_zchronod.config.node.gossip_fanout).cloned().collect()
+        };
+        for (node_id, src) in peers {
+            sync::send_digest(arc_zchronod.clone(), &node_id, Link::Outer, src).await;
+        }
+    }
+}
+
+/// Announce this node to the configured bootstrap peers.
+pub(crate) async fn bootstrap(arc_zchronod: ZchronodArc) {
+    for peer in arc_zchronod.config.node.bootstrap_peers.iter() {
+        match peer.parse::<SocketAddr>() {
+            Ok(dest) => handshake::send_hello(arc_zchronod.clone(), Action::Write, &[], Link::Outer, dest).await,
+            Err(err) => error!("Bootstrap peer {} is not a socket address, err={:?}", peer, err),
+        }
+    }
+}
+
+/// Periodically send a heartbeat with our signed clock to every known peer,
+/// and greet again the peers silent for longer than the heartbeat timeout.
+pub(crate) async fn heartbeat_loop(arc_zchronod: ZchronodArc) {
+    let heartbeat_interval = arc_zchronod.config.node.heartbeat_interval;
+    if heartbeat_interval == 0 {
+        info!("Heartbeat is disabled");
+        return;
+    }
+    let mut interval = tokio::time::interval(Duration::from_millis(heartbeat_interval));
     loop {
-        let mut buf = [0; 65535];
-        let (n, src) = arc_zchronod.socket.recv_from(&mut buf).await.unwrap();
-        let msg = prost::bytes::Bytes::copy_from_slice(&buf[..n]);
-        if let Ok(m) = Innermsg::decode(msg) {
-            info!("Received: message from identity: {:?}, action: {:?}", m.identity(), m.action());
-            handle_msg(arc_zchronod.clone(), m, src).await;
-        } else {
-            info!("No action, only support innermsg type between vlc & p2p modules at now");
+        interval.tick().await;
+        let peers: Vec<(String, SocketAddr)> = arc_zchronod.peers.read().await.iter().map(|(id, peer)| (id.clone(), peer.address)).collect();
+        for (node_id, src) in peers {
+            sync::send_heartbeat(arc_zchronod.clone(), &node_id, Link::Outer, src).await;
         }
+        // peers the registry counts as down no longer hold back cache eviction
+        let now = tools::helper::get_time_ms();
+        let live: HashSet<String> = arc_zchronod.peers.read().await.iter()
+            .filter(|(_, peer)| peer.is_alive(now, arc_zchronod.config.node.heartbeat_timeout))
+            .map(|(id, _)| id.clone())
+            .collect();
+        arc_zchronod.state.write().await.retain_peers(|id| live.contains(id));
+        for (node_id, src) in arc_zchronod.network.due_reconnects().await {
+            info!("Reconnect peer {} at {}", node_id, src);
+            let to = hex::decode(&node_id).unwrap_or_default();
+            handshake::send_hello(arc_zchronod.clone(), Action::Write, &to, Link::Outer, src).await;
+        }
+    }
+}
+
+/// Drop event triggers held too long in the causal buffer and fetch the
+/// missing events from their senders instead.
+pub(crate) async fn pending_sweep_loop(arc_zchronod: ZchronodArc) {
+    let pending_timeout = arc_zchronod.config.node.pending_timeout;
+    if pending_timeout == 0 {
+        return;
+    }
+    let mut interval = tokio::time::interval(Duration::from_millis(pending_timeout));
+    loop {
+        interval.tick().await;
+        write::expire_pending(arc_zchronod.clone()).await;
     }
 }
 
-pub(crate) async fn handle_msg(arc_zchronod: ZchronodArc, inner_msg: Innermsg, src: SocketAddr) {
+/// Send again the reliable messages whose ack is overdue.
+pub(crate) async fn retransmit_loop(arc_zchronod: ZchronodArc) {
+    if !arc_zchronod.config.net.reliable {
+        return;
+    }
+    let mut interval = tokio::time::interval(Duration::from_millis((arc_zchronod.config.net.ack_timeout / 2).max(1)));
+    loop {
+        interval.tick().await;
+        arc_zchronod.network.retransmit().await;
+    }
+}
+
+pub(crate) async fn handle_msg(arc_zchronod: ZchronodArc, inner_msg: Innermsg, link: Link, src: SocketAddr) {
     let arc_zchronod_clone = arc_zchronod.clone();
     let inner_msg_clone = inner_msg.clone();
     
@@ -37,32 +127,41 @@ pub(crate) async fn handle_msg(arc_zchronod: ZchronodArc, inner_msg: Innermsg, s
         let p2p_msg_clone = p2p_msg.clone();
         match inner_msg.identity() {
             Identity::Client => {
-                let _ = tokio::spawn(async move {
+                tokio::spawn(async move {
                     handle_cli_msg(inner_msg_clone, &p2p_msg_clone, arc_zchronod_clone, src).await;
                 });
             },
             Identity::Server => {
-                let _ = tokio::spawn(async move {
-                    handle_srv_msg(inner_msg_clone, &p2p_msg_clone, arc_zchronod_clone, src).await
+                tokio::spawn(async move {
+                    handle_srv_msg(inner_msg_clone, &p2p_msg_clone, arc_zchronod_clone, link, src).await
+                });
+            },
+            Identity::Init => {
+                tokio::spawn(async move {
+                    handshake::handle_init_msg(arc_zchronod_clone, inner_msg_clone, &p2p_msg_clone, link, src).await
                 });
             },
-            Identity::Init => {todo!()},
         }
     } else {
         info!("p2p_msg is empty, no action triggered!");
     }
 }
 
-async fn handle_srv_msg(inner_msg: Innermsg, p2p_msg: &ZMessage, arc_zchronod: ZchronodArc, src: SocketAddr) {
+async fn handle_srv_msg(inner_msg: Innermsg, p2p_msg: &ZMessage, arc_zchronod: ZchronodArc, link: Link, src: SocketAddr) {
+    if !endorsement::check_srv_endorsements(&arc_zchronod, &inner_msg).await {
+        return;
+    }
     match p2p_msg.r#type() {
         ZType::Clock => {
             let clock_msg = prost::bytes::Bytes::from(p2p_msg.data.clone());
             let z_clock = ZClock::decode(clock_msg).unwrap_or(ZClock::default());
             match z_clock.r#type() {
-                ClockType::EventTrigger => write::handle_srv_event_trigger(arc_zchronod, z_clock, inner_msg, p2p_msg, src).await,
-                ClockType::DiffReq => todo!(),
-                ClockType::DiffRsp => todo!(),
-                ClockType::ActiveSync => todo!(),
+                ClockType::EventTrigger => write::handle_srv_event_trigger(arc_zchronod, z_clock, inner_msg, link, src).await,
+                ClockType::DiffReq => sync::handle_srv_diff_req(arc_zchronod, z_clock, link, src).await,
+                ClockType::DiffRsp => sync::handle_srv_diff_resp(arc_zchronod, z_clock, link, src).await,
+                ClockType::ActiveSync => sync::handle_srv_active_sync(arc_zchronod, z_clock, link, src).await,
+                ClockType::Digest => sync::handle_srv_digest(arc_zchronod, z_clock, link, src).await,
+                ClockType::Heartbeat => sync::handle_srv_heartbeat(arc_zchronod, z_clock, src).await,
             }
         }
         _ => error!("Server message: just support ZType::Clock for state sync & clock update!"),
@@ -76,7 +175,7 @@ async fn handle_cli_msg(inner_msg: Innermsg, p2p_msg: &ZMessage, arc_zchronod: Z
             let arc_zchronod_clone = arc_zchronod.clone();
             let inner_msg_clone = inner_msg.clone();
             let p2p_msg_clone = p2p_msg.clone();
-            let _ = tokio::spawn(async move {
+            tokio::spawn(async move {
                 // MESSAGE_COUNT.fetch_add(1, Ordering::SeqCst);
                 // debug!("Total recv read msg: {}", MESSAGE_COUNT.load(Ordering::SeqCst));
                 read::handle_cli_read_msg(arc_zchronod_clone, inner_msg_clone, &p2p_msg_clone, src).await;
@@ -1,10 +1,3 @@
-mod zchronod;
-mod node_factory;
-mod storage;
-mod vlc;
-mod handler;
-mod api;
-
 use std::path::PathBuf;
 use db_sql::pg::pg_client::setup_db;
 use tools::tokio_zchronod;
@@ -14,8 +7,8 @@ use node_api::error::ZchronodConfigError;
 use tracing::*;
 use node_api::config::ZchronodConfig;
 use tracing_subscriber::EnvFilter;
-use crate::zchronod::ZchronodArc;
-use crate::zchronod::Zchronod;
+use zebclock::zchronod::ZchronodArc;
+use zebclock::zchronod::Zchronod;
 
 #[derive(StructOpt)]
 struct ZchronodCli {
@@ -24,6 +17,9 @@ struct ZchronodCli {
 
     #[structopt(long = "init_pg", help = "Init & refresh pg, caution: new db & new table")]
     init_pg: Option<String>,
+
+    #[structopt(long = "gen_key", help = "Print a new secret_key and its node_id for the config file")]
+    gen_key: bool,
 }
 
 fn main() {
@@ -41,6 +37,13 @@ async fn async_main() {
     let mut help_info = true;
     let args = ZchronodCli::from_args();
 
+    if args.gen_key {
+        help_info = false;
+        let keypair = tools::crypto::generate_keypair();
+        println!("secret_key: \"{}\"", keypair.display_secret());
+        println!("node_id: \"{}\"", tools::crypto::node_id_of(&keypair));
+    }
+
     // init pg db
     if let Some(pg_conn_str) = args.init_pg {
         help_info = false;
@@ -115,6 +118,10 @@ fn construct_node_config(config_path: PathBuf) -> config::ZchronodConfig {
             error!("nodeid illegal, must be hex format, and 64 bits");
             std::process::exit(ERROR_CODE);
         }
+        Err(ZchronodConfigError::IllegalSecretKey) => {
+            error!("secret_key illegal, must be the hex secp256k1 secret key whose x-only public key is nodeid, exec: zchronod --gen_key for a new pair");
+            std::process::exit(ERROR_CODE);
+        }
         result => {
             result.expect("failed to load zhronod config")
         }
@@ -1,9 +1,12 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::sync::Arc;
-use node_api::config::ZchronodConfig;
+use std::time::Duration;
+use node_api::config::{InnerTransport, PushMode, ZchronodConfig};
 use node_api::error::ZchronodResult;
-use tokio::net::UdpSocket;
-use tokio::sync::RwLock;
+use tokio::sync::{Mutex, RwLock};
 use tokio::task::JoinHandle;
+use p2p::{Network, P2pError, P2pResult, QuicTransport, ReconnectPolicy, RetransmitPolicy, TcpTransport, Transport, UdpTransport, UnixMode, UnixTransport};
+use tools::crypto::{keypair_from_hex, node_id_of};
 use crate::{handler, storage};
 use crate::zchronod::{ServerState, Zchronod, ZchronodArc};
 
@@ -25,19 +28,52 @@ impl ZchronodFactory {
     pub async fn create_zchronod(config: ZchronodConfig) -> ZchronodArc {
         let cfg = Arc::new(config.clone());
         let address = config.net.inner_p2p.clone();
-        let node_id = config.node.node_id.clone().unwrap_or_default();
-        let socket = UdpSocket::bind(address).await.unwrap();
-        let state = RwLock::new(ServerState::new(node_id, cfg.node.cache_msg_maximum));
+        let secret_key = config.node.secret_key.clone().unwrap_or_default();
+        let keypair = keypair_from_hex(&secret_key).expect("secret_key is checked when loading config");
+        let node_id = node_id_of(&keypair);
+        let inner = inner_transport(&config, &address).await.expect("failed to bind inner_p2p");
+        let outer: Option<Box<dyn Transport>> = match (config.net.push_mode, config.net.outer_p2p.as_deref()) {
+            (PushMode::Direct, Some(outer)) => Some(Box::new(UdpTransport::bind(outer).await.expect("failed to bind outer_p2p"))),
+            _ => None,
+        };
+        let mut network = Network::new(inner, outer, reconnect_policy(&config))
+            .with_fragment_limits(config.net.max_message_size, Duration::from_millis(config.net.reassembly_timeout));
+        if config.net.reliable {
+            network = network.with_retransmit(RetransmitPolicy {
+                ack_timeout: Duration::from_millis(config.net.ack_timeout),
+                max_attempts: config.net.retransmit_maximum,
+                ..Default::default()
+            });
+        }
+        let state = RwLock::new(
+            ServerState::new(node_id.clone(), keypair, cfg.node.cache_msg_maximum)
+                .with_clock_kind(cfg.node.clock_kind)
+                .with_pending_limits(cfg.node.pending_maximum, cfg.node.pending_timeout)
+                .with_clock_epochs(clock_epochs(&cfg)),
+        );
         let storage = storage::Storage::new(cfg.clone()).await;
+        storage.load_node_dictionary().await.expect("failed to load node dictionary");
+        storage.backfill_total_order().await.expect("failed to backfill message total order keys");
+        let peers = storage.load_peers().await.unwrap_or_default();
+        for (node_id, peer) in peers.iter() {
+            network.add_peer(node_id, peer.address).await;
+        }
         let latest_clockinfo = storage.get_last_clock().await;
-        if let Ok(clockinfo) = latest_clockinfo {
+        if let Ok(mut clockinfo) = latest_clockinfo {
+            // keep the persisted events when the configured clock kind changed
+            clockinfo.clock = clockinfo.clock.to_kind(cfg.node.clock_kind);
             state.write().await.clock_info = clockinfo;
         }
         let zchronod = Zchronod {
             config: cfg,
-            socket,
+            node_id,
+            network,
             storage,
             state,
+            peers: RwLock::new(peers),
+            broadcast_clocks: Mutex::new(VecDeque::new()),
+            trigger_clocks: RwLock::new(HashMap::new()),
+            hello_times: Mutex::new(HashMap::new()),
         };
 
         Arc::new(zchronod)
@@ -46,11 +82,16 @@ impl ZchronodFactory {
     pub async fn initialize_node(self) -> ZchronodResult<ZchronodArc> {
         let arc_zchronod = ZchronodFactory::create_zchronod(self.config.clone()).await;
 
-        let mut join_handles: Vec<JoinHandle<()>> = Vec::new();
-        join_handles.push(tokio::spawn(handler::p2p_event_loop(arc_zchronod.clone())));
-        
-        // start client websocket
-        join_handles.push(tokio::spawn(handler::handle_incoming_ws_msg(self.config.net.ws_url)));
+        let join_handles: Vec<JoinHandle<()>> = vec![
+            tokio::spawn(handler::p2p_event_loop(arc_zchronod.clone())),
+            tokio::spawn(handler::gossip_loop(arc_zchronod.clone())),
+            tokio::spawn(handler::heartbeat_loop(arc_zchronod.clone())),
+            tokio::spawn(handler::pending_sweep_loop(arc_zchronod.clone())),
+            tokio::spawn(handler::retransmit_loop(arc_zchronod.clone())),
+            tokio::spawn(handler::bootstrap(arc_zchronod.clone())),
+            // start client websocket
+            tokio::spawn(handler::handle_incoming_ws_msg(self.config.net.ws_url)),
+        ];
 
         for handle in join_handles {
             handle.await.unwrap();
@@ -58,4 +99,37 @@ impl ZchronodFactory {
 
         Ok(arc_zchronod)
     }
-}
\ No newline at end of file
+}
+
+/// Transport of the zeb link selected in the config.
+async fn inner_transport(config: &ZchronodConfig, address: &str) -> P2pResult<Box<dyn Transport>> {
+    Ok(match config.net.inner_transport {
+        InnerTransport::Udp => Box::new(UdpTransport::bind(address).await?),
+        InnerTransport::Tcp => Box::new(TcpTransport::bind(address).await?),
+        InnerTransport::Quic => {
+            // required by validate_config
+            let tls = config.net.quic_cert.as_deref().zip(config.net.quic_key.as_deref())
+                .ok_or_else(|| P2pError::QuicError("quic_cert and quic_key are not set".to_owned()))?;
+            Box::new(QuicTransport::bind(address, tls).await?)
+        }
+        InnerTransport::UnixDatagram => Box::new(UnixTransport::bind(address, UnixMode::Datagram, config.net.inner_socket_mode).await?),
+        InnerTransport::UnixStream => Box::new(UnixTransport::bind(address, UnixMode::Stream, config.net.inner_socket_mode).await?),
+    })
+}
+
+/// Silent peers count as disconnected after the heartbeat timeout and are
+/// re-greeted from one heartbeat interval on.
+fn reconnect_policy(config: &ZchronodConfig) -> ReconnectPolicy {
+    ReconnectPolicy {
+        peer_timeout: config.node.heartbeat_timeout.into(),
+        backoff_base: config.node.heartbeat_interval.into(),
+        ..Default::default()
+    }
+}
+
+/// Clock epochs of the config, keyed by lowercase node id like the clocks.
+fn clock_epochs(config: &ZchronodConfig) -> Vec<BTreeMap<String, u128>> {
+    config.node.clock_epochs.iter()
+        .map(|epoch| epoch.retired.iter().map(|(id, last)| (id.to_lowercase(), *last)).collect())
+        .collect()
+}
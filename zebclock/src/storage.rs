@@ -1,13 +1,15 @@
-use std::{sync::Arc, time::Duration};
-use chrono::{Local, NaiveDateTime};
-use db_sql::pg::entities::{merge_logs, z_messages};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use chrono::{DateTime, Local};
+use db_sql::pg::counter;
+use db_sql::pg::entities::{merge_logs, node_dictionary, peers, z_messages};
 use node_api::config::ZchronodConfig;
 // use db_sql::api::{DbKindZchronod, DbWrite};
-use db_sql::pg::entities::{clock_infos, prelude::{ClockInfos, MergeLogs, ZMessages}};
+use db_sql::pg::entities::{clock_infos, prelude::{ClockInfos, MergeLogs, NodeDictionary, Peers, ZMessages}};
 use protos::zmessage::ZMessage as ProtoZMessage;
 use sea_orm::*;
-use crate::vlc::ClockInfo;
+use crate::vlc::{ClockInfo, LogicalClock, NODE_DICTIONARY};
 use crate::vlc::MergeLog;
+use crate::zchronod::PeerInfo;
 use tracing::{error, info};
 
 pub struct Storage {
@@ -41,17 +43,13 @@ impl Storage {
     
     // postgre inner api
     pub async fn sinker_clock(&self, message_id: String, raw_message: Vec<u8>, clock_info: &ClockInfo) {
-        let clock_str = serde_json::to_string(&clock_info.clock).unwrap();
-        let naive_datetime = NaiveDateTime::from_timestamp_millis(clock_info.create_at.try_into().unwrap());
-        let clock_info = clock_infos::ActiveModel {
-            clock: ActiveValue::Set(clock_str.clone()),
-            clock_hash: ActiveValue::Set(clock_info.clock_hash.clone()),
-            node_id: ActiveValue::Set(clock_info.node_id.to_owned()),
-            message_id: ActiveValue::Set(message_id),
-            raw_message: ActiveValue::Set(raw_message),
-            event_count: ActiveValue::Set(clock_info.count.try_into().unwrap()),
-            create_at: ActiveValue::Set(naive_datetime),
-            ..Default::default()
+        self.sinker_node_dictionary().await;
+        let clock_info = match clock_info_model(message_id, raw_message, clock_info) {
+            Err(err) => {
+                error!("Insert clock_info error, err: {}", err);
+                return;
+            }
+            Ok(clock_info) => clock_info,
         };
         let res = ClockInfos::insert(clock_info).exec(self.pg_db.as_ref()).await;
         if let Err(err) = res {
@@ -59,16 +57,113 @@ impl Storage {
         }
     }
 
+    /// Persist the node ids interned since the last call, so compact clock
+    /// indices stay stable across restarts.
+    pub async fn sinker_node_dictionary(&self) {
+        let (unsaved, len) = {
+            let dictionary = NODE_DICTIONARY.read().unwrap_or_else(std::sync::PoisonError::into_inner);
+            (dictionary.unsaved(), dictionary.len())
+        };
+        if unsaved.is_empty() {
+            return;
+        }
+        let entries = unsaved.into_iter().map(|(index, node_id)| node_dictionary::ActiveModel {
+            node_index: ActiveValue::Set(index as i32),
+            node_id: ActiveValue::Set(node_id),
+        });
+        let res = NodeDictionary::insert_many(entries)
+            .on_conflict(sea_query::OnConflict::column(node_dictionary::Column::NodeIndex).do_nothing().to_owned())
+            .exec_without_returning(self.pg_db.as_ref())
+            .await;
+        match res {
+            Err(err) => error!("Insert node_dictionary error, err: {}", err),
+            Ok(_) => NODE_DICTIONARY.write().unwrap_or_else(std::sync::PoisonError::into_inner).mark_saved(len),
+        }
+    }
+
+    /// Restore the persisted node dictionary, must run before any clock is interned.
+    pub async fn load_node_dictionary(&self) -> Result<usize, DbErr> {
+        let entries = NodeDictionary::find()
+            .order_by_asc(node_dictionary::Column::NodeIndex)
+            .all(self.pg_db.as_ref()).await;
+
+        match entries {
+            Err(err) => {
+                error!("Query node_dictionary error, err: {}", err);
+                Err(err)
+            }
+            Ok(entries) => {
+                let mut dictionary = NODE_DICTIONARY.write().unwrap_or_else(std::sync::PoisonError::into_inner);
+                for entry in entries.iter() {
+                    if !dictionary.restore(entry.node_index as u32, &entry.node_id) {
+                        error!("Skip node_dictionary entry {} -> {}, indices are not dense", entry.node_index, entry.node_id);
+                    }
+                }
+                Ok(dictionary.len())
+            }
+        }
+    }
+
+    /// Insert or update the registry entry of a peer.
+    pub async fn sinker_peer(&self, node_id: &str, peer: &PeerInfo) {
+        let last_heartbeat = i64::try_from(peer.last_heartbeat).ok()
+            .filter(|millis| *millis > 0)
+            .and_then(DateTime::from_timestamp_millis)
+            .map(|dt| dt.naive_utc());
+        let peer = peers::ActiveModel {
+            node_id: ActiveValue::Set(node_id.to_owned()),
+            address: ActiveValue::Set(peer.address.to_string()),
+            public_key: ActiveValue::Set(node_id.to_owned()),
+            last_heartbeat: ActiveValue::Set(last_heartbeat),
+            last_clock: ActiveValue::Set(peer.last_clock.as_ref().and_then(|clock| serde_json::to_string(clock).ok())),
+        };
+        let res = Peers::insert(peer)
+            .on_conflict(
+                sea_query::OnConflict::column(peers::Column::NodeId)
+                    .update_columns([peers::Column::Address, peers::Column::LastHeartbeat, peers::Column::LastClock])
+                    .to_owned(),
+            )
+            .exec_without_returning(self.pg_db.as_ref())
+            .await;
+        if let Err(err) = res {
+            error!("Insert peer error, err: {}", err);
+        }
+    }
+
+    /// Restore the membership registry, entries with an unreadable address are skipped.
+    pub async fn load_peers(&self) -> Result<HashMap<String, PeerInfo>, DbErr> {
+        let peers = Peers::find().all(self.pg_db.as_ref()).await;
+        match peers {
+            Err(err) => {
+                error!("Query peers error, err: {}", err);
+                Err(err)
+            }
+            Ok(peers) => Ok(peers.into_iter().filter_map(|peer| {
+                let Ok(address) = peer.address.parse() else {
+                    error!("Skip peer {}, address {} is not a socket address", peer.node_id, peer.address);
+                    return None;
+                };
+                let info = PeerInfo {
+                    address,
+                    last_heartbeat: peer.last_heartbeat.map_or(0, |dt| u128::try_from(dt.and_utc().timestamp_millis()).unwrap_or(0)),
+                    last_clock: peer.last_clock.as_deref().and_then(|clock| serde_json::from_str(clock).ok()),
+                    acked_clock: None,
+                };
+                Some((peer.node_id, info))
+            }).collect()),
+        }
+    }
+
     pub async fn sinker_merge_log(&self, fclock_info: &ClockInfo, tclock_info: &ClockInfo) {
         let f_hash_hex = fclock_info.clock_hash.clone();
         let e_hash_hex = tclock_info.clock_hash.clone();
         let now = Local::now().timestamp_millis();
-        let naive_datetime = NaiveDateTime::from_timestamp_millis(now).unwrap();
+        let naive_datetime = DateTime::from_timestamp_millis(now).unwrap().naive_utc();
         let merge_log = merge_logs::ActiveModel {
             from_id: ActiveValue::Set(fclock_info.node_id.to_owned()),
             to_id: ActiveValue::Set(tclock_info.node_id.to_owned()),
-            start_count: ActiveValue::Set(fclock_info.count.to_owned().try_into().unwrap()),
-            end_count: ActiveValue::Set(tclock_info.count.to_owned().try_into().unwrap()),
+            start_count: ActiveValue::Set(counter::to_numeric(fclock_info.count)),
+            end_count: ActiveValue::Set(counter::to_numeric(tclock_info.count)),
             s_clock_hash: ActiveValue::Set(f_hash_hex),
             e_clock_hash: ActiveValue::Set(e_hash_hex),
             merge_at: ActiveValue::Set(naive_datetime),
@@ -80,22 +175,9 @@ impl Storage {
         }
     }
 
-    pub async fn sinker_zmessage(&self, zmessage: ProtoZMessage) {
-        let msg_id = hex::encode(zmessage.id);
-        let pub_key_hex = hex::encode(zmessage.public_key);
-        let from_hex = hex::encode(zmessage.from);
-        let to_hex = hex::encode(zmessage.to);
-        let zmessage = z_messages::ActiveModel {
-            message_id: ActiveValue::Set(msg_id),
-            version: ActiveValue::Set(Some(zmessage.version as i32)),
-            r#type: ActiveValue::Set(zmessage.r#type),
-            public_key: ActiveValue::Set(Some(pub_key_hex)),
-            data: ActiveValue::Set(zmessage.data),
-            signature: ActiveValue::Set(Some(zmessage.signature)),
-            from: ActiveValue::Set(from_hex),
-            to: ActiveValue::Set(to_hex),
-            ..Default::default()
-        };
+    /// Store a message with the signed clock of the node it was first written to.
+    pub async fn sinker_zmessage(&self, zmessage: ProtoZMessage, origin: &ClockInfo) {
+        let zmessage = zmessage_to_model(zmessage, origin);
         let res = ZMessages::insert(zmessage).exec(self.pg_db.as_ref()).await;
         if let Err(err) = res {
             error!("Insert z_messages error, err: {}", err);
@@ -114,10 +196,7 @@ impl Storage {
                 error!("RecordNotFound: Clock not found for msg_id: {}", msg_id);
                 Err(err)
             }
-            Ok(Some(clock)) => {
-                let clock_ret: ClockInfo = clock.into();
-                Ok(clock_ret)
-            }
+            Ok(Some(clock)) => ClockInfo::try_from(clock),
         }
     }
 
@@ -136,10 +215,7 @@ impl Storage {
                 info!("RecordNotFound: empty clockinfo table");
                 Err(err)
             }
-            Ok(Some(clock)) => {
-                let clock_ret: ClockInfo = clock.into();
-                Ok(clock_ret)
-            }
+            Ok(Some(clock)) => ClockInfo::try_from(clock),
         }
     }
 
@@ -162,6 +238,61 @@ impl Storage {
         }
     }
 
+    /// Events of a node after start_count in event order, each as the clock info
+    /// right after the event, the message it applied and the message origin clock.
+    /// Knowledge only merges (empty message id) are not events and are skipped.
+    pub async fn get_events_since(&self, node_id: &str, start_count: u128, number: u64) -> Result<Vec<(ClockInfo, ProtoZMessage, ClockInfo)>, DbErr> {
+        let clock_infos = ClockInfos::find()
+            .filter(clock_infos::Column::NodeId.eq(node_id))
+            .filter(clock_infos::Column::EventCount.gt(counter::to_numeric(start_count)))
+            .filter(clock_infos::Column::MessageId.ne(""))
+            .order_by_asc(clock_infos::Column::EventCount)
+            .limit(number)
+            .all(self.pg_db.as_ref()).await;
+
+        let clocks = match clock_infos {
+            Err(err) => {
+                error!("Query events by node_id and count error, err: {}", err);
+                return Err(err);
+            }
+            Ok(clocks) => clocks,
+        };
+
+        let msg_ids: Vec<String> = clocks.iter().map(|clock| clock.message_id.clone()).collect();
+        let zmessages = ZMessages::find()
+            .filter(z_messages::Column::MessageId.is_in(msg_ids))
+            .all(self.pg_db.as_ref()).await;
+
+        match zmessages {
+            Err(err) => {
+                error!("Query z_messages of events error, err: {}", err);
+                Err(err)
+            }
+            Ok(zmessages) => {
+                let mut zmessages: HashMap<String, z_messages::Model> = zmessages.into_iter().map(|msg| (msg.message_id.clone(), msg)).collect();
+                let mut events = Vec::with_capacity(clocks.len());
+                for clock in clocks {
+                    let Some(msg) = zmessages.remove(&clock.message_id) else {
+                        error!("ZMessage of event {} not found, msg_id: {}", clock.event_count, clock.message_id);
+                        continue;
+                    };
+                    let clock_info = ClockInfo::try_from(clock)?;
+                    let origin = self.model_origin(&msg).unwrap_or_else(|| clock_info.clone());
+                    events.push((clock_info, self.model_to_zmessage(msg), origin));
+                }
+                Ok(events)
+            }
+        }
+    }
+
+    pub async fn has_zmessage(&self, msg_id: &str) -> bool {
+        let count = ZMessages::find()
+            .filter(z_messages::Column::MessageId.eq(msg_id))
+            .count(self.pg_db.as_ref())
+            .await;
+        matches!(count, Ok(n) if n > 0)
+    }
+
     pub async fn get_clocks_by_keyid(&self, start_id: u64, number: u64) -> Result<Vec<ClockInfo>, DbErr> {
         let clock_infos= ClockInfos::find()
             .filter(clock_infos::Column::Id.gt(start_id))
@@ -173,10 +304,7 @@ impl Storage {
                 error!("Query clockinfos by start_id error, err: {}", err);
                 Err(err)
             }
-            Ok(clocks) => {
-                let clock_rets = clocks.iter().map(|clock| (clock.clone().into())).collect();
-                Ok(clock_rets)
-            }
+            Ok(clocks) => clocks.into_iter().map(ClockInfo::try_from).collect(),
         }
     }
 
@@ -191,10 +319,7 @@ impl Storage {
                 error!("Query merge_logs by start_id error, err: {}", err);
                 Err(err)
             }
-            Ok(logs) => {
-                let mergelog_rets = logs.iter().map(|log| (log.clone().into())).collect();
-                Ok(mergelog_rets)
-            }
+            Ok(logs) => logs.into_iter().map(MergeLog::try_from).collect(),
         }
     }
 
@@ -216,6 +341,45 @@ impl Storage {
         }
     }
 
+    /// Page of messages in the deterministic total order of their origin clocks,
+    /// see `ClockInfo::total_cmp`, skipping the first `offset`. Messages stored
+    /// without an origin clock come first, ordered by message id.
+    pub async fn get_zmessages_in_total_order(&self, offset: u64, number: u64) -> Result<Vec<ProtoZMessage>, DbErr> {
+        let zmessages = ZMessages::find()
+            .order_by_asc(z_messages::Column::OrderEvents)
+            .order_by_asc(z_messages::Column::OrderHash)
+            .order_by_asc(z_messages::Column::MessageId)
+            .offset(offset)
+            .limit(number)
+            .all(self.pg_db.as_ref()).await;
+
+        match zmessages {
+            Err(err) => {
+                error!("Query z_messages for total order error, err: {}", err);
+                Err(err)
+            }
+            Ok(zmessages) => Ok(zmessages.into_iter().map(|msg| self.model_to_zmessage(msg)).collect()),
+        }
+    }
+
+    /// Fill in the total order key of messages stored before it was persisted.
+    pub async fn backfill_total_order(&self) -> Result<(), DbErr> {
+        let zmessages = ZMessages::find()
+            .filter(z_messages::Column::OrderEvents.is_null())
+            .all(self.pg_db.as_ref()).await?;
+        if !zmessages.is_empty() {
+            info!("Backfill the total order key of {} messages", zmessages.len());
+        }
+        for msg in zmessages {
+            let (order_events, order_hash) = total_order_key(self.model_origin(&msg).as_ref());
+            let mut zmessage: z_messages::ActiveModel = msg.into();
+            zmessage.order_events = ActiveValue::Set(Some(order_events));
+            zmessage.order_hash = ActiveValue::Set(Some(order_hash));
+            zmessage.update(self.pg_db.as_ref()).await?;
+        }
+        Ok(())
+    }
+
     pub async fn get_clocks_counts(&self) -> Result<u64, DbErr> {
         let clocks_count= ClockInfos::find()
             .count(self.pg_db.as_ref())
@@ -264,6 +428,10 @@ impl Storage {
         }
     }
 
+    fn model_origin(&self, zmessage: &z_messages::Model) -> Option<ClockInfo> {
+        zmessage.origin_clock.as_deref().and_then(|origin| serde_json::from_str(origin).ok())
+    }
+
     fn model_to_zmessage(&self, zmessage: z_messages::Model) -> ProtoZMessage {
         let msg_id = hex::decode(zmessage.message_id).unwrap_or_else(|_| Vec::new());
         let pub_key_bytes = hex::decode(zmessage.public_key.unwrap()).unwrap_or_else(|_| Vec::new());
@@ -279,7 +447,96 @@ impl Storage {
             signature: zmessage.signature.unwrap(),
             from: from_bytes,
             to: to_bytes,
+            signature_scheme: zmessage.signature_scheme,
         }
     }
 
-}
\ No newline at end of file
+}
+
+/// Row of a message, every field the message id covers is stored.
+fn zmessage_to_model(zmessage: ProtoZMessage, origin: &ClockInfo) -> z_messages::ActiveModel {
+    let (order_events, order_hash) = total_order_key(Some(origin));
+    z_messages::ActiveModel {
+        message_id: ActiveValue::Set(hex::encode(zmessage.id)),
+        version: ActiveValue::Set(Some(zmessage.version as i32)),
+        r#type: ActiveValue::Set(zmessage.r#type),
+        public_key: ActiveValue::Set(Some(hex::encode(zmessage.public_key))),
+        data: ActiveValue::Set(zmessage.data),
+        signature: ActiveValue::Set(Some(zmessage.signature)),
+        from: ActiveValue::Set(hex::encode(zmessage.from)),
+        to: ActiveValue::Set(hex::encode(zmessage.to)),
+        origin_clock: ActiveValue::Set(serde_json::to_string(origin).ok()),
+        order_events: ActiveValue::Set(Some(order_events)),
+        order_hash: ActiveValue::Set(Some(order_hash)),
+        signature_scheme: ActiveValue::Set(zmessage.signature_scheme),
+        ..Default::default()
+    }
+}
+
+/// Sort key of a message in the total order: event total and canonical hash
+/// of its origin clock, hex hashes of equal length sort like their bytes.
+/// Messages without origin clock get the smallest key on every node.
+fn total_order_key(origin: Option<&ClockInfo>) -> (prelude::BigDecimal, String) {
+    match origin {
+        Some(origin) => (counter::to_numeric(origin.clock.events()), origin.clock.hash_hex()),
+        None => (counter::to_numeric(0), String::new()),
+    }
+}
+
+/// Row of a clock info, counters are stored losslessly, timestamps that don't
+/// fit their columns are an error.
+fn clock_info_model(message_id: String, raw_message: Vec<u8>, clock_info: &ClockInfo) -> Result<clock_infos::ActiveModel, DbErr> {
+    let out_of_range = |column: &str| DbErr::Type(format!("clock_infos.{} out of range, clock_hash {}", column, clock_info.clock_hash));
+    let create_at = i64::try_from(clock_info.create_at).map_err(|_| out_of_range("create_at"))?;
+    let naive_datetime = DateTime::from_timestamp_millis(create_at).ok_or_else(|| out_of_range("create_at"))?.naive_utc();
+    Ok(clock_infos::ActiveModel {
+        clock: ActiveValue::Set(serde_json::to_string(&clock_info.clock).map_err(|err| DbErr::Json(err.to_string()))?),
+        clock_hash: ActiveValue::Set(clock_info.clock_hash.clone()),
+        node_id: ActiveValue::Set(clock_info.node_id.to_owned()),
+        message_id: ActiveValue::Set(message_id),
+        raw_message: ActiveValue::Set(raw_message),
+        event_count: ActiveValue::Set(counter::to_numeric(clock_info.count)),
+        create_at: ActiveValue::Set(Some(naive_datetime)),
+        prev_clock_hash: ActiveValue::Set(Some(clock_info.prev_clock_hash.clone())),
+        signature: ActiveValue::Set(Some(clock_info.signature.clone())),
+        hlc_physical: ActiveValue::Set(Some(i64::try_from(clock_info.hlc.physical).map_err(|_| out_of_range("hlc_physical"))?)),
+        hlc_logical: ActiveValue::Set(Some(i32::try_from(clock_info.hlc.logical).map_err(|_| out_of_range("hlc_logical"))?)),
+        sign_version: ActiveValue::Set(Some(i32::try_from(clock_info.sign_version).map_err(|_| out_of_range("sign_version"))?)),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protos::zmessage::{SignatureScheme, ZType};
+    use crate::vlc::Clock;
+    use crate::api::message_id::{message_id, verify_message_id};
+
+    #[tokio::test]
+    async fn zmessage_roundtrip_keeps_id() {
+        let mut msg = ProtoZMessage {
+            version: 1,
+            r#type: ZType::Zchat.into(),
+            public_key: vec![7; 32],
+            data: b"hello".to_vec(),
+            signature: vec![9; 64],
+            from: vec![1; 32],
+            to: vec![2; 32],
+            signature_scheme: SignatureScheme::Ed25519.into(),
+            ..Default::default()
+        };
+        msg.id = message_id(&msg);
+
+        let origin = ClockInfo::new(Clock::new(), String::new(), hex::encode([1; 32]), String::new(), 0);
+        let mut row = zmessage_to_model(msg.clone(), &origin);
+        row.id = ActiveValue::Set(1);
+        let row = row.try_into_model().unwrap();
+        let storage = Storage {
+            pg_db: Arc::new(MockDatabase::new(DatabaseBackend::Postgres).append_query_results([vec![row]]).into_connection()),
+        };
+        let loaded = storage.get_zmessages_by_keyid(0, 1).await.unwrap();
+        assert_eq!(loaded, vec![msg]);
+        assert!(verify_message_id(&loaded[0]));
+    }
+}
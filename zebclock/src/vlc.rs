@@ -3,133 +3,376 @@
 //! Please reference create/vlc package. Update contents: 
 //! * 1. Turn the index id to string type.
 //! * 2. New add the clockinfo & mergelog object.
+//! * 3. Clock implementation is picked per node, see `Clock`.
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
+use std::borrow::Cow;
+use std::sync::{LazyLock, PoisonError, RwLock};
 use std::cmp;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use db_sql::pg::entities::clock_infos::Model as ClockInfoModel;
+use node_api::config::ClockKind;
 use db_sql::pg::entities::merge_logs::Model as MergeLogModel;
-use protos::vlc::ClockInfo as ProtoClockInfo;
+use db_sql::pg::counter;
+use sea_orm::DbErr;
+use protos::vlc::{Clock as ProtoClock, ClockInfo as ProtoClockInfo, ClockKind as ProtoClockKind, Dot as ProtoDot};
+use tools::crypto::{sign_payload, verify_payload, NodeKeypair};
+
+pub use ::vlc::{CompactClock, HybridLogicalClock, LogicalClock, NodeDictionary, CLOCK_ENCODING_VERSION};
+
+/// Domain tag prefixed to the clock info signing payload.
+pub const CLOCK_INFO_SIGN_DOMAIN: &[u8] = b"zebclock/clock_info/v2";
+/// Domain of clock infos signed before the hybrid clock, whose payload ends at prev_clock_hash.
+pub const CLOCK_INFO_SIGN_DOMAIN_V1: &[u8] = b"zebclock/clock_info/v1";
+/// Signing payload version new clock infos are signed with.
+pub const CLOCK_INFO_SIGN_VERSION: u32 = 2;
+
+/// Dimension holding the summed final counts of the folded retired nodes.
+/// Node ids are 64 hex characters, so it never collides with one.
+pub const EPOCH_DIMENSION: &str = "epoch";
+
+/// Retired node ids with their final counts, one map per agreed epoch.
+pub type ClockEpochs = [BTreeMap<String, u128>];
+
+type VectorClock = ::vlc::VectorClock<String>;
+type DottedVersionVector = ::vlc::DottedVersionVector<String>;
+
+/// Node ids interned for compact clocks, shared by the whole process and
+/// persisted by `Storage::sinker_node_dictionary`.
+pub static NODE_DICTIONARY: LazyLock<RwLock<NodeDictionary>> = LazyLock::new(|| RwLock::new(NodeDictionary::new()));
+
+/// Node clock, keyed by node id, in the implementation picked by
+/// `NodeConfig::clock_kind`. Serialized untagged, so rows written by a
+/// vector clock node (`{"values": ...}`) keep deserializing. A compact clock
+/// serializes, encodes and hashes exactly like the vector clock it stands for.
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+#[serde(untagged, from = "ClockRepr")]
+pub enum Clock {
+    Dotted(DottedVersionVector),
+    Vector(VectorClock),
+    #[serde(serialize_with = "serialize_compact")]
+    Compact(CompactClock),
+}
 
-#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
-pub struct Clock {
-    pub values: HashMap<String, u128>,
+/// Serialized form of `Clock`, the kind is told by the presence of dots.
+/// Serde untagged enums can't buffer u128 values, hence the explicit shape.
+#[derive(Deserialize)]
+struct ClockRepr {
+    values: BTreeMap<String, u128>,
+    dots: Option<std::collections::BTreeSet<(String, u128)>>,
 }
 
-impl PartialOrd for Clock {
-    fn partial_cmp(&self, other: &Clock) -> Option<cmp::Ordering> {
-        let mut less = false;
-        let mut greater = false;
-
-        for (id, value) in &self.values {
-            let other_value = other.values.get(id);
-            if other_value.is_none() || value > other_value.unwrap() {
-                greater = true;
-            } else if value < other_value.unwrap() {
-                less = true;
-            }
+impl From<ClockRepr> for Clock {
+    fn from(repr: ClockRepr) -> Self {
+        match repr.dots {
+            Some(dots) => Clock::Dotted(DottedVersionVector { values: repr.values, dots }),
+            None => Clock::Vector(VectorClock { values: repr.values }),
         }
+    }
+}
 
-        for (id, _) in &other.values {
-            if self.values.get(id).is_none() {
-                less = true;
-            }
-        }
+fn serialize_compact<S: Serializer>(clock: &CompactClock, serializer: S) -> Result<S::Ok, S::Error> {
+    VectorClock { values: resolve_compact(clock) }.serialize(serializer)
+}
 
-        if less && greater {
-            None
-        } else if less {
-            Some(cmp::Ordering::Less)
-        } else if greater {
-            Some(cmp::Ordering::Greater)
-        } else {
-            Some(cmp::Ordering::Equal)
+fn intern_compact(values: &BTreeMap<String, u128>) -> CompactClock {
+    let mut dictionary = NODE_DICTIONARY.write().unwrap_or_else(PoisonError::into_inner);
+    CompactClock::from_dims(values.iter().map(|(id, value)| (dictionary.intern(id), *value)))
+}
+
+/// Compact form of a clock without interning: node ids the dictionary
+/// doesn't know get indices past its end, distinct from every known one.
+fn lookup_compact(values: &BTreeMap<String, u128>) -> CompactClock {
+    let dictionary = NODE_DICTIONARY.read().unwrap_or_else(PoisonError::into_inner);
+    let mut unknown = dictionary.len() as u32;
+    CompactClock::from_dims(values.iter().map(|(id, value)| {
+        let index = dictionary.index(id).unwrap_or_else(|| {
+            unknown += 1;
+            unknown - 1
+        });
+        (index, *value)
+    }))
+}
+
+fn resolve_compact(clock: &CompactClock) -> BTreeMap<String, u128> {
+    let dictionary = NODE_DICTIONARY.read().unwrap_or_else(PoisonError::into_inner);
+    clock.dims().iter()
+        .filter_map(|(index, value)| dictionary.resolve(*index).map(|id| (id.to_owned(), *value)))
+        .collect()
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PartialOrd for Clock {
+    fn partial_cmp(&self, other: &Clock) -> Option<cmp::Ordering> {
+        match (self, &*other.compare_form(self.kind())) {
+            (Clock::Vector(c1), Clock::Vector(c2)) => c1.partial_cmp(c2),
+            (Clock::Dotted(c1), Clock::Dotted(c2)) => c1.partial_cmp(c2),
+            (Clock::Compact(c1), Clock::Compact(c2)) => c1.partial_cmp(c2),
+            _ => unreachable!("converted to the same kind"),
         }
     }
 }
 
 impl Clock {
-    /// Create a new clock.
+    /// Create a new vector clock.
     pub fn new() -> Self {
-        Self {
-            values: HashMap::new(),
+        Clock::Vector(VectorClock::new())
+    }
+
+    /// Create a new clock of the given kind.
+    pub fn with_kind(kind: ClockKind) -> Self {
+        match kind {
+            ClockKind::Vector => Clock::Vector(VectorClock::new()),
+            ClockKind::DottedVersionVector => Clock::Dotted(DottedVersionVector::new()),
+            ClockKind::Compact => Clock::Compact(CompactClock::new()),
+        }
+    }
+
+    pub fn kind(&self) -> ClockKind {
+        match self {
+            Clock::Vector(_) => ClockKind::Vector,
+            Clock::Dotted(_) => ClockKind::DottedVersionVector,
+            Clock::Compact(_) => ClockKind::Compact,
+        }
+    }
+
+    /// Convert to another kind. Dots are dropped when converting to a vector
+    /// or compact clock, so the result never claims an event the clock didn't see.
+    pub fn to_kind(&self, kind: ClockKind) -> Clock {
+        if self.kind() == kind {
+            return self.clone();
+        }
+        match kind {
+            ClockKind::Vector => Clock::Vector(VectorClock { values: self.values().into_owned() }),
+            ClockKind::DottedVersionVector => Clock::Dotted(DottedVersionVector {
+                values: self.values().into_owned(),
+                dots: Default::default(),
+            }),
+            ClockKind::Compact => Clock::Compact(intern_compact(&self.values())),
+        }
+    }
+
+    /// This clock as `kind` to compare with a clock of that kind. Unlike
+    /// `to_kind` it never interns, so unverified clocks leave the node
+    /// dictionary alone and a compact clock compares index by index.
+    fn compare_form(&self, kind: ClockKind) -> Cow<'_, Clock> {
+        match kind {
+            _ if self.kind() == kind => Cow::Borrowed(self),
+            ClockKind::Compact => Cow::Owned(Clock::Compact(lookup_compact(&self.values()))),
+            _ => Cow::Owned(self.to_kind(kind)),
+        }
+    }
+
+    /// Contiguous event count per node id.
+    pub fn values(&self) -> Cow<'_, BTreeMap<String, u128>> {
+        match self {
+            Clock::Vector(c) => Cow::Borrowed(&c.values),
+            Clock::Dotted(c) => Cow::Borrowed(&c.values),
+            Clock::Compact(c) => Cow::Owned(resolve_compact(c)),
+        }
+    }
+
+    /// Isolated events beyond the contiguous counts, always empty for a vector clock.
+    pub fn dots(&self) -> Vec<(String, u128)> {
+        match self {
+            Clock::Dotted(c) => c.dots.iter().cloned().collect(),
+            _ => Vec::new(),
         }
     }
 
-    /// Increment the clock
-    pub fn inc(&mut self, id: String) {
-        let value = self.values.entry(id).or_insert(0);
-        *value += 1;
+    /// Dimensions that differ from `base`, zero for a dimension `base` has
+    /// and self doesn't. None for dotted version vectors, which go in full.
+    pub fn delta_from(&self, base: &Clock) -> Option<BTreeMap<String, u128>> {
+        if self.kind() == ClockKind::DottedVersionVector || base.kind() == ClockKind::DottedVersionVector {
+            return None;
+        }
+        let (values, base_values) = (self.values(), base.values());
+        let mut delta: BTreeMap<String, u128> = values.iter()
+            .filter(|(id, value)| base_values.get(*id) != Some(*value))
+            .map(|(id, value)| (id.clone(), *value))
+            .collect();
+        for id in base_values.keys().filter(|id| !values.contains_key(*id)) {
+            delta.insert(id.clone(), 0);
+        }
+        Some(delta)
     }
 
-    /// Get the clock count by id
-    pub fn get(&mut self, id: String) -> u128 {
-        let value = self.values.entry(id).or_insert(0);
-        *value
+    /// Rebuild the clock a delta was taken from, self being the delta base.
+    /// The result is a vector clock, the delta is not verified yet.
+    pub fn apply_delta(&self, delta: &BTreeMap<String, u128>) -> Clock {
+        let mut values = self.values().into_owned();
+        for (id, value) in delta {
+            match value {
+                0 => values.remove(id),
+                _ => values.insert(id.clone(), *value),
+            };
+        }
+        Clock::Vector(VectorClock { values })
     }
 
-    /// Reset the clock.
-    pub fn clear(&mut self) {
-        self.values.clear();
+    /// Number of leading epochs folded into this clock.
+    pub fn epoch_level(&self, epochs: &ClockEpochs) -> usize {
+        let base = self.get(&EPOCH_DIMENSION.to_owned());
+        let mut folded = 0;
+        epochs.iter()
+            .take_while(|retired| {
+                folded += retired.values().sum::<u128>();
+                folded <= base
+            })
+            .count()
     }
 
-    /// Merge the clock with other clocks.
-    pub fn merge(&mut self, others: &Vec<&Clock>) {
-        for &clock in others {
-            for (id, value) in &clock.values {
-                let v = self.values.entry(id.clone()).or_insert(0);
-                *v = std::cmp::max(*v, *value);
+    /// Fold the next epochs, up to `level`, whose retired nodes are all seen
+    /// up to their final count. Returns true if the clock changed.
+    pub fn fold_epochs(&mut self, epochs: &ClockEpochs, level: usize) -> bool {
+        let start = self.epoch_level(epochs);
+        let folded = epochs.iter().take(level).skip(start)
+            .take_while(|retired| retired.iter().all(|(id, last)| *last > 0 && self.get(id) == *last))
+            .count();
+        if folded == 0 {
+            return false;
+        }
+        let mut values = self.values().into_owned();
+        for retired in epochs.iter().skip(start).take(folded) {
+            for id in retired.keys() {
+                values.remove(id);
             }
+            *values.entry(EPOCH_DIMENSION.to_owned()).or_default() += retired.values().sum::<u128>();
         }
+        *self = self.with_values(values);
+        true
     }
 
-    /// Diff is local clock minus another clock
-    pub fn diff(&self, other: &Clock) -> Clock {
-        let mut ret = Clock::new();
-        for (id, v1) in &self.values {
-            let v2 = other.values.get(id).unwrap_or(&0);
-            if v1 > v2 {
-                ret.values.insert(id.clone(), v1-v2);
-            } else {
-                ret.values.insert(id.clone(), 0);
+    /// This clock at epoch `level`: later epochs are expanded back to the
+    /// final counts of their retired nodes, earlier ones folded when possible,
+    /// so it compares and merges with a clock at that level.
+    pub fn align_epochs(&self, epochs: &ClockEpochs, level: usize) -> Clock {
+        let mut clock = self.clone();
+        let current = clock.epoch_level(epochs);
+        if current > level {
+            let mut values = clock.values().into_owned();
+            for retired in &epochs[level..current] {
+                values.extend(retired.iter().map(|(id, last)| (id.clone(), *last)));
+                let base = values.entry(EPOCH_DIMENSION.to_owned()).or_default();
+                *base -= retired.values().sum::<u128>();
+                if *base == 0 {
+                    values.remove(EPOCH_DIMENSION);
+                }
             }
+            clock = clock.with_values(values);
+        } else {
+            clock.fold_epochs(epochs, level);
         }
-        ret
+        clock
     }
 
-    /// return index key of clock
-    pub fn index_key(&self) -> String {
-        let mut key: String = String::new();
-        for (index, value) in &self.values {
-            key = format!("{}{}-{}-", key, index, value);
+    /// Same kind with other contiguous counts, dots of dropped dimensions go too.
+    fn with_values(&self, values: BTreeMap<String, u128>) -> Clock {
+        match self {
+            Clock::Dotted(c) => Clock::Dotted(DottedVersionVector {
+                dots: c.dots.iter().filter(|(id, _)| values.contains_key(id)).cloned().collect(),
+                values,
+            }),
+            _ => Clock::Vector(VectorClock { values }).to_kind(self.kind()),
         }
-        key
     }
 
     /// return common base clock of two clock
     pub fn base_common(&self, other: &Clock) -> Clock {
-        let mut ret = Clock::new();
-        for (id, v1) in &self.values {
-            let v2 = other.values.get(id).unwrap_or(&0);
-            if v1 <= v2 {
-                ret.values.insert(id.clone(), *v1);
-            } else {
-                ret.values.insert(id.clone(), *v2);
+        match (self, &*other.compare_form(self.kind())) {
+            (Clock::Vector(c1), Clock::Vector(c2)) => Clock::Vector(c1.base_common(c2)),
+            (Clock::Dotted(c1), Clock::Dotted(c2)) => Clock::Dotted(c1.base_common(c2)),
+            (Clock::Compact(c1), Clock::Compact(c2)) => Clock::Compact(c1.base_common(c2)),
+            _ => unreachable!("converted to the same kind"),
+        }
+    }
+}
+
+impl LogicalClock for Clock {
+    type Id = String;
+
+    fn inc(&mut self, id: String) {
+        match self {
+            Clock::Vector(c) => c.inc(id),
+            Clock::Dotted(c) => c.inc(id),
+            Clock::Compact(c) => c.inc(NODE_DICTIONARY.write().unwrap_or_else(PoisonError::into_inner).intern(&id)),
+        }
+    }
+
+    fn get(&self, id: &String) -> u128 {
+        match self {
+            Clock::Vector(c) => c.get(id),
+            Clock::Dotted(c) => c.get(id),
+            Clock::Compact(c) => {
+                let index = NODE_DICTIONARY.read().unwrap_or_else(PoisonError::into_inner).index(id);
+                index.map_or(0, |index| c.get(&index))
             }
         }
-        ret
     }
 
-    /// return true when all value is zero in clock dimensions
-    pub fn is_genesis(&self) -> bool {
-        let sum: u128 = self.values.values().sum();
-        sum == 0
+    /// Other clocks are converted to the kind of this clock first, node ids
+    /// new to a compact clock are interned: merge verified clocks only.
+    fn merge(&mut self, others: &[&Clock]) {
+        let kind = self.kind();
+        for other in others {
+            match (&mut *self, other.to_kind(kind)) {
+                (Clock::Vector(c1), Clock::Vector(c2)) => c1.merge(&[&c2]),
+                (Clock::Dotted(c1), Clock::Dotted(c2)) => c1.merge(&[&c2]),
+                (Clock::Compact(c1), Clock::Compact(c2)) => c1.merge(&[&c2]),
+                _ => unreachable!("converted to the same kind"),
+            }
+        }
+    }
+
+    fn diff(&self, other: &Clock) -> Clock {
+        match (self, &*other.compare_form(self.kind())) {
+            (Clock::Vector(c1), Clock::Vector(c2)) => Clock::Vector(c1.diff(c2)),
+            (Clock::Dotted(c1), Clock::Dotted(c2)) => Clock::Dotted(c1.diff(c2)),
+            (Clock::Compact(c1), Clock::Compact(c2)) => Clock::Compact(c1.diff(c2)),
+            _ => unreachable!("converted to the same kind"),
+        }
+    }
+
+    fn is_genesis(&self) -> bool {
+        match self {
+            Clock::Vector(c) => c.is_genesis(),
+            Clock::Dotted(c) => c.is_genesis(),
+            Clock::Compact(c) => c.is_genesis(),
+        }
+    }
+
+    fn events(&self) -> u128 {
+        match self {
+            Clock::Vector(c) => c.events(),
+            Clock::Dotted(c) => c.events(),
+            Clock::Compact(c) => c.events(),
+        }
+    }
+
+    /// Vector clock layout: version byte, u32 big-endian dimension count,
+    /// then every non-zero dimension sorted by node id as (u32 BE id length,
+    /// utf-8 id bytes, u128 BE value). A dotted version vector appends its
+    /// dots, see `vlc::DottedVersionVector`. A compact clock encodes as the
+    /// vector clock of its resolved node ids, indices are node local.
+    fn encode(&self) -> Vec<u8> {
+        match self {
+            Clock::Vector(c) => c.encode(),
+            Clock::Dotted(c) => c.encode(),
+            Clock::Compact(c) => VectorClock { values: resolve_compact(c) }.encode(),
+        }
     }
-    
 }
 
 /// Clock info sinker to db.
 /// id is server node id, count is the event count in this server.
+/// signature is the node schnorr signature over `signing_payload`, chained by prev_clock_hash.
+/// hlc is the hybrid logical clock stamp of the state, ordering it close to wall-clock time.
+/// sign_version is the signing payload version the signature was made with.
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ClockInfo {
     pub clock: Clock,
@@ -138,12 +381,60 @@ pub struct ClockInfo {
     pub message_id: String,
     pub count: u128,
     pub create_at: u128,
+    pub prev_clock_hash: String,
+    pub signature: String,
+    pub hlc: HybridLogicalClock,
+    pub sign_version: u32,
 }
 
 impl ClockInfo {
     pub fn new(clock: Clock, clock_hash: String,node_id: String, message_id: String, count: u128) -> Self {
         let create_at = tools::helper::get_time_ms();
-        Self { clock, clock_hash, node_id, message_id, count, create_at }
+        Self { clock, clock_hash, node_id, message_id, count, create_at, prev_clock_hash: String::new(), signature: String::new(), hlc: HybridLogicalClock::default(), sign_version: CLOCK_INFO_SIGN_VERSION }
+    }
+
+    /// Bytes covered by the node signature: domain tag, canonical clock,
+    /// event count, message id, previous clock hash and hybrid clock.
+    /// Variable length parts are u32 BE length prefixed, count is u128 BE.
+    /// Version 1 payloads have the v1 domain and no hybrid clock.
+    pub fn signing_payload(&self) -> Vec<u8> {
+        let clock = self.clock.encode();
+        let message_id = hex::decode(&self.message_id).unwrap_or_default();
+        let prev_clock_hash = hex::decode(&self.prev_clock_hash).unwrap_or_default();
+
+        let domain = if self.sign_version == 1 { CLOCK_INFO_SIGN_DOMAIN_V1 } else { CLOCK_INFO_SIGN_DOMAIN };
+        let mut buf = domain.to_vec();
+        buf.extend_from_slice(&(clock.len() as u32).to_be_bytes());
+        buf.extend_from_slice(&clock);
+        buf.extend_from_slice(&self.count.to_be_bytes());
+        buf.extend_from_slice(&(message_id.len() as u32).to_be_bytes());
+        buf.extend_from_slice(&message_id);
+        buf.extend_from_slice(&(prev_clock_hash.len() as u32).to_be_bytes());
+        buf.extend_from_slice(&prev_clock_hash);
+        if self.sign_version != 1 {
+            buf.extend_from_slice(&self.hlc.encode());
+        }
+        buf
+    }
+
+    /// Deterministic total order of events, see `vlc::total_cmp`, message id breaks the last tie.
+    pub fn total_cmp(&self, other: &ClockInfo) -> cmp::Ordering {
+        ::vlc::total_cmp(&self.clock, &other.clock).then_with(|| self.message_id.cmp(&other.message_id))
+    }
+
+    /// Sign the clock info with the node keypair.
+    pub fn sign(&mut self, keypair: &NodeKeypair) {
+        self.sign_version = CLOCK_INFO_SIGN_VERSION;
+        let signature = sign_payload(keypair, &self.signing_payload());
+        self.signature = hex::encode(signature);
+    }
+
+    /// Verify the signature against node_id, which is the signer x-only public key.
+    pub fn verify_signature(&self) -> bool {
+        match (hex::decode(&self.node_id), hex::decode(&self.signature)) {
+            (Ok(public_key), Ok(signature)) => verify_payload(&public_key, &self.signing_payload(), &signature),
+            _ => false,
+        }
     }
 }
 
@@ -152,46 +443,101 @@ impl From<&ProtoClockInfo> for ClockInfo {
         let clock = protobuf_clock_info
             .clock
             .as_ref()
-            .map(|c| {
-                Clock {
-                    values: c
-                        .values
-                        .iter()
-                        .map(|(k, v)| (k.clone(), *v as u128))
-                        .collect(),
-                }
-            }).unwrap();
+            .map(Clock::from)
+            .unwrap_or_default();
         
-        let clock_hash_hex = hex::encode(&protobuf_clock_info.clock_hash);
+        // derive the hash from the canonical encoding instead of trusting the wire value
+        let clock_hash_hex = clock.hash_hex();
         let node_id = hex::encode(&protobuf_clock_info.node_id);
         let message_id = hex::encode(&protobuf_clock_info.message_id);
-        let count = protobuf_clock_info.count;
+        let count = protobuf_clock_info.event_count.as_ref().map_or(0, u128::from);
         let create_at = protobuf_clock_info.create_at;
+        let prev_clock_hash = hex::encode(&protobuf_clock_info.prev_clock_hash);
+        let signature = hex::encode(&protobuf_clock_info.signature);
+        let hlc = protobuf_clock_info.hlc.as_ref()
+            .map(|hlc| HybridLogicalClock::new(hlc.physical, hlc.logical))
+            .unwrap_or_default();
+        let sign_version = match protobuf_clock_info.sign_version {
+            0 => legacy_sign_version(protobuf_clock_info.hlc.is_some()),
+            version => version,
+        };
 
         ClockInfo {
             clock,
             clock_hash: clock_hash_hex,
             node_id,
             message_id,
-            count: count.into(),
+            count,
             create_at: create_at.into(),
+            prev_clock_hash,
+            signature,
+            hlc,
+            sign_version,
         }
     }
 }
 
-impl From<ClockInfoModel> for ClockInfo {
-    fn from(model: ClockInfoModel) -> Self {
-        let clock: Clock = serde_json::from_str(&model.clock).unwrap_or_else(|_| Clock::default());
-        let create_at = model.create_at.map(|dt| dt.and_utc().timestamp_millis() as u128).unwrap_or(0);
+/// Version of a clock info signed before versions were recorded: only
+/// v2 signers sent or stored a hybrid clock.
+fn legacy_sign_version(has_hlc: bool) -> u32 {
+    if has_hlc { 2 } else { 1 }
+}
 
-        ClockInfo {
+impl From<&ProtoClock> for Clock {
+    fn from(protobuf_clock: &ProtoClock) -> Self {
+        let values = protobuf_clock.counters.iter().map(|(k, v)| (k.clone(), u128::from(v))).collect();
+        match protobuf_clock.kind() {
+            ProtoClockKind::Vector => Clock::Vector(VectorClock { values }),
+            ProtoClockKind::DottedVersionVector => Clock::Dotted(DottedVersionVector {
+                values,
+                dots: protobuf_clock.dots.iter().map(|dot| (dot.id.clone(), dot.counter.as_ref().map_or(0, u128::from))).collect(),
+            }),
+        }
+    }
+}
+
+impl From<&Clock> for ProtoClock {
+    fn from(clock: &Clock) -> Self {
+        // compact clocks go on the wire as the vector clock they stand for
+        let kind = match clock.kind() {
+            ClockKind::Vector | ClockKind::Compact => ProtoClockKind::Vector,
+            ClockKind::DottedVersionVector => ProtoClockKind::DottedVersionVector,
+        };
+        ProtoClock {
+            counters: clock.values().iter().map(|(k, v)| (k.clone(), (*v).into())).collect(),
+            dots: clock.dots().into_iter().map(|(id, counter)| ProtoDot { id, counter: Some(counter.into()) }).collect(),
+            kind: kind.into(),
+        }
+    }
+}
+
+impl TryFrom<ClockInfoModel> for ClockInfo {
+    type Error = DbErr;
+
+    fn try_from(model: ClockInfoModel) -> Result<Self, DbErr> {
+        let clock: Clock = serde_json::from_str(&model.clock)
+            .map_err(|err| DbErr::Json(format!("clock_infos.clock of id {} unreadable, err={}", model.id, err)))?;
+        let create_at = model.create_at.map(|dt| dt.and_utc().timestamp_millis()).unwrap_or(0);
+        let out_of_range = |column: &str| DbErr::Type(format!("clock_infos.{} out of range, id {}", column, model.id));
+
+        Ok(ClockInfo {
+            count: counter::from_numeric(&model.event_count)?,
+            create_at: u128::try_from(create_at).map_err(|_| out_of_range("create_at"))?,
+            hlc: HybridLogicalClock::new(
+                u64::try_from(model.hlc_physical.unwrap_or(0)).map_err(|_| out_of_range("hlc_physical"))?,
+                u32::try_from(model.hlc_logical.unwrap_or(0)).map_err(|_| out_of_range("hlc_logical"))?,
+            ),
             clock,
             clock_hash: model.clock_hash,
             node_id: model.node_id,
             message_id: model.message_id,
-            count: model.event_count as u128,
-            create_at,
-        }
+            prev_clock_hash: model.prev_clock_hash.unwrap_or_default(),
+            signature: model.signature.unwrap_or_default(),
+            sign_version: match model.sign_version {
+                Some(version) => u32::try_from(version).map_err(|_| out_of_range("sign_version"))?,
+                None => legacy_sign_version(model.hlc_physical.is_some()),
+            },
+        })
     }
 }
 
@@ -208,19 +554,22 @@ pub struct MergeLog {
     pub merge_at: u128,
 }
 
-impl From<MergeLogModel> for MergeLog {
-    fn from(model: MergeLogModel) -> Self {
-        let merge_at = model.merge_at.and_utc().timestamp_millis() as u128;
+impl TryFrom<MergeLogModel> for MergeLog {
+    type Error = DbErr;
+
+    fn try_from(model: MergeLogModel) -> Result<Self, DbErr> {
+        let merge_at = u128::try_from(model.merge_at.and_utc().timestamp_millis())
+            .map_err(|_| DbErr::Type(format!("merge_logs.merge_at out of range, id {}", model.id)))?;
 
-        MergeLog {
+        Ok(MergeLog {
             from_id: model.from_id,
             to_id: model.to_id,
-            start_count: model.start_count as u128,
-            end_count: model.end_count as u128,
+            start_count: counter::from_numeric(&model.start_count)?,
+            end_count: counter::from_numeric(&model.end_count)?,
             s_clock_hash: model.s_clock_hash,
             e_clock_hash: model.e_clock_hash,
             merge_at,
-        }
+        })
     }
 }
 
@@ -233,7 +582,7 @@ mod tests {
         let mut c = Clock::new();
         c.inc("0".to_owned());
         c.inc("0".to_owned());
-        assert_eq!(c.values.get(&"0".to_owned()), Some(&2));
+        assert_eq!(c.values().get("0"), Some(&2));
     }
 
     #[test]
@@ -266,10 +615,213 @@ mod tests {
         assert_eq!(c1.partial_cmp(&c3), None);
         assert_eq!(c2.partial_cmp(&c3), None);
 
-        c1.merge(&vec![&c2, &c3]);
+        c1.merge(&[&c2, &c3]);
         assert_eq!(c2.partial_cmp(&c1), Some(cmp::Ordering::Less));
         assert_eq!(c1.partial_cmp(&c2), Some(cmp::Ordering::Greater));
         assert_eq!(c3.partial_cmp(&c1), Some(cmp::Ordering::Less));
         assert_eq!(c1.partial_cmp(&c3), Some(cmp::Ordering::Greater));
     }
+
+    #[test]
+    fn clock_encode_hash() {
+        let mut c1 = Clock::new();
+        c1.inc("b".to_owned());
+        c1.inc("a".to_owned());
+        let mut c2 = Clock::new();
+        c2.inc("a".to_owned());
+        c2.inc("b".to_owned());
+        if let Clock::Vector(c) = &mut c2 {
+            c.values.insert("c".to_owned(), 0);
+        }
+
+        assert_eq!(c1.encode(), c2.encode());
+        assert_eq!(c1.hash_hex(), c2.hash_hex());
+        assert_eq!(c1.hash_hex().len(), 64);
+
+        let mut expected = vec![CLOCK_ENCODING_VERSION];
+        expected.extend_from_slice(&2u32.to_be_bytes());
+        for id in ["a", "b"] {
+            expected.extend_from_slice(&1u32.to_be_bytes());
+            expected.extend_from_slice(id.as_bytes());
+            expected.extend_from_slice(&1u128.to_be_bytes());
+        }
+        assert_eq!(c1.encode(), expected);
+
+        c2.inc("c".to_owned());
+        assert_ne!(c1.hash_hex(), c2.hash_hex());
+    }
+
+    #[test]
+    fn clock_info_sign_verify() {
+        let keypair = tools::crypto::keypair_from_hex("5b3e8f2d1c7a4b6e9f0d2c8a7b5e3f1d4c6a8b0e2f4d6c8a1b3e5f7d9c0a2b4e").unwrap();
+        let node_id = tools::crypto::node_id_of(&keypair);
+        let mut clock = Clock::new();
+        clock.inc(node_id.clone());
+        let mut info = ClockInfo::new(clock.clone(), clock.hash_hex(), node_id.clone(), hex::encode([1u8; 32]), 1);
+        assert!(!info.verify_signature());
+
+        info.sign(&keypair);
+        assert!(info.verify_signature());
+
+        let mut tampered = info.clone();
+        tampered.count = 2;
+        assert!(!tampered.verify_signature());
+
+        let mut tampered = info.clone();
+        tampered.clock.inc(node_id);
+        assert!(!tampered.verify_signature());
+
+        let mut tampered = info.clone();
+        tampered.prev_clock_hash = hex::encode([2u8; 32]);
+        assert!(!tampered.verify_signature());
+
+        let mut tampered = info.clone();
+        tampered.hlc.tick(u64::MAX);
+        assert!(!tampered.verify_signature());
+
+        // signed by a node predating the hybrid clock, with the v1 payload
+        let mut legacy = info;
+        legacy.sign_version = 1;
+        legacy.hlc = HybridLogicalClock::default();
+        legacy.signature = hex::encode(sign_payload(&keypair, &legacy.signing_payload()));
+        assert!(legacy.signing_payload().starts_with(CLOCK_INFO_SIGN_DOMAIN_V1));
+        assert!(legacy.verify_signature());
+        let mut proto = crate::api::response::clockinfo_to_proto()(legacy.clone());
+        assert!(ClockInfo::from(&proto).verify_signature());
+        // as sent by a v1 node, without hybrid clock and version
+        proto.hlc = None;
+        proto.sign_version = 0;
+        assert!(ClockInfo::from(&proto).verify_signature());
+        legacy.sign_version = CLOCK_INFO_SIGN_VERSION;
+        assert!(!legacy.verify_signature());
+    }
+
+    #[test]
+    fn clock_info_model_with_bad_clock() {
+        let mut clock = Clock::new();
+        clock.inc("a".to_owned());
+        let model = ClockInfoModel {
+            id: 1,
+            clock: serde_json::to_string(&clock).unwrap(),
+            clock_hash: clock.hash_hex(),
+            node_id: "a".to_owned(),
+            message_id: String::new(),
+            raw_message: Vec::new(),
+            event_count: counter::to_numeric(1),
+            create_at: None,
+            prev_clock_hash: None,
+            signature: None,
+            hlc_physical: None,
+            hlc_logical: None,
+            sign_version: None,
+        };
+        assert_eq!(ClockInfo::try_from(model.clone()).unwrap().clock, clock);
+        let corrupt = ClockInfoModel { clock: "{\"values\":".to_owned(), ..model };
+        assert!(matches!(ClockInfo::try_from(corrupt), Err(DbErr::Json(_))));
+    }
+
+    #[test]
+    fn clock_kinds_roundtrip() {
+        let mut vector = Clock::new();
+        vector.inc("a".to_owned());
+        let mut dotted = Clock::with_kind(ClockKind::DottedVersionVector);
+        dotted.inc("a".to_owned());
+        dotted.inc("b".to_owned());
+
+        // compare and merge across kinds go through the kind of the left clock
+        assert_eq!(vector.partial_cmp(&dotted), Some(cmp::Ordering::Less));
+        vector.merge(&[&dotted]);
+        assert_eq!(vector.kind(), ClockKind::Vector);
+        assert_eq!(vector.partial_cmp(&dotted), Some(cmp::Ordering::Equal));
+
+        for clock in [vector, dotted] {
+            let json = serde_json::to_string(&clock).unwrap();
+            assert_eq!(serde_json::from_str::<Clock>(&json).unwrap(), clock);
+            assert_eq!(Clock::from(&ProtoClock::from(&clock)), clock);
+        }
+    }
+
+    #[test]
+    fn compact_clock_as_vector() {
+        let mut vector = Clock::new();
+        vector.inc("b".to_owned());
+        vector.inc("a".to_owned());
+        let mut compact = vector.to_kind(ClockKind::Compact);
+        assert_eq!(compact.kind(), ClockKind::Compact);
+        assert_eq!(compact.hash_hex(), vector.hash_hex());
+        assert_eq!(serde_json::to_string(&compact).unwrap(), serde_json::to_string(&vector).unwrap());
+        assert_eq!(ProtoClock::from(&compact), ProtoClock::from(&vector));
+
+        compact.inc("a".to_owned());
+        assert_eq!(compact.get(&"a".to_owned()), 2);
+        assert_eq!(vector.partial_cmp(&compact), Some(cmp::Ordering::Less));
+        vector.merge(&[&compact]);
+        assert_eq!(vector.hash_hex(), compact.hash_hex());
+    }
+
+    #[test]
+    fn compact_compare_does_not_intern() {
+        let mut compact = Clock::with_kind(ClockKind::Compact);
+        compact.inc("compare-known".to_owned());
+        let mut unverified = Clock::new();
+        unverified.inc("compare-known".to_owned());
+        unverified.inc("compare-unknown".to_owned());
+
+        assert_eq!(compact.partial_cmp(&unverified), Some(cmp::Ordering::Less));
+        assert_eq!(compact.base_common(&unverified), compact);
+        assert!(compact.diff(&unverified).is_genesis());
+        assert_eq!(compact.apply_delta(&unverified.delta_from(&compact).unwrap()).kind(), ClockKind::Vector);
+        assert_eq!(NODE_DICTIONARY.read().unwrap().index("compare-unknown"), None);
+
+        compact.merge(&[&unverified]);
+        assert!(NODE_DICTIONARY.read().unwrap().index("compare-unknown").is_some());
+        assert_eq!(compact.partial_cmp(&unverified), Some(cmp::Ordering::Equal));
+    }
+
+    #[test]
+    fn clock_delta_roundtrip() {
+        let mut base = Clock::new();
+        base.inc("a".to_owned());
+        base.inc("b".to_owned());
+        let mut clock = Clock::new();
+        clock.merge(&[&base]);
+        clock.inc("a".to_owned());
+        clock.inc("c".to_owned());
+
+        let delta = clock.delta_from(&base).unwrap();
+        assert_eq!(delta.len(), 2);
+        assert_eq!(base.apply_delta(&delta).hash_hex(), clock.hash_hex());
+
+        // going back drops the dimension the base didn't have
+        let delta = base.delta_from(&clock).unwrap();
+        assert_eq!(delta.get("c"), Some(&0));
+        assert_eq!(clock.apply_delta(&delta).hash_hex(), base.hash_hex());
+
+        assert!(Clock::with_kind(ClockKind::DottedVersionVector).delta_from(&base).is_none());
+    }
+
+    #[test]
+    fn fold_retired_dimensions() {
+        let epochs = vec![BTreeMap::from([("b".to_owned(), 2)]), BTreeMap::from([("c".to_owned(), 1)])];
+        let mut clock = Clock::new();
+        clock.inc("a".to_owned());
+        clock.inc("b".to_owned());
+
+        // b is not seen up to its final count yet
+        assert!(!clock.fold_epochs(&epochs, usize::MAX));
+        clock.inc("b".to_owned());
+        clock.inc("c".to_owned());
+        let full = clock.clone();
+        assert!(clock.fold_epochs(&epochs, usize::MAX));
+        assert_eq!(clock.values().into_owned(), BTreeMap::from([("a".to_owned(), 1), (EPOCH_DIMENSION.to_owned(), 3)]));
+        assert_eq!(clock.epoch_level(&epochs), 2);
+        assert_eq!(clock.events(), full.events());
+
+        // aligned to the level of a peer that only folded the first epoch
+        let aligned = clock.align_epochs(&epochs, 1);
+        assert_eq!(aligned.get(&"c".to_owned()), 1);
+        assert_eq!(aligned.get(&EPOCH_DIMENSION.to_owned()), 2);
+        assert_eq!(full.align_epochs(&epochs, 1).partial_cmp(&aligned), Some(cmp::Ordering::Equal));
+        assert_eq!(aligned.align_epochs(&epochs, 0).partial_cmp(&full), Some(cmp::Ordering::Equal));
+    }
 }
@@ -1,19 +1,35 @@
-use crate::vlc::ClockInfo;
+use crate::vlc::{ClockInfo, LogicalClock};
 use crate::{node_factory::ZchronodFactory, storage::Storage, vlc::Clock};
-use node_api::config::ZchronodConfig;
+use node_api::config::{ClockKind, ZchronodConfig};
+use p2p::Link;
+use protos::innermsg::Innermsg;
+use protos::vlc::ClockDelta;
 use protos::zmessage::ZMessage;
-use tools::helper::sha256_str_to_hex;
-use std::collections::{BTreeMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::net::SocketAddr;
 use std::{cmp, sync::Arc};
-use tokio::net::UdpSocket;
-use tokio::sync::RwLock;
+use tokio::sync::{Mutex, RwLock};
+use tools::crypto::NodeKeypair;
 use tracing::*;
 
+/// Event trigger clocks kept per node as possible clock delta bases, on the
+/// sending and on the receiving side.
+pub const DELTA_BASE_HISTORY: usize = 16;
+
+/// Multiple of the cache maximum above which the oldest items are evicted
+/// even if not every peer has seen them yet.
+pub const CACHE_HARD_FACTOR: u64 = 4;
+
 pub struct Zchronod {
     pub config: Arc<ZchronodConfig>,
-    pub socket: UdpSocket,
+    pub node_id: String,
+    pub network: p2p::Network,                          // inner socket, plus the outer one in direct push mode
     pub storage: Storage,
     pub state: RwLock<ServerState>,
+    pub peers: RwLock<HashMap<String, PeerInfo>>,       // membership registry, known server node id -> info
+    pub broadcast_clocks: Mutex<VecDeque<Clock>>,       // recent event trigger clocks, the ones peers can ack
+    pub trigger_clocks: RwLock<HashMap<String, VecDeque<Clock>>>, // recent event trigger clocks of each server node
+    pub hello_times: Mutex<HashMap<String, u64>>,       // timestamp of the last accepted hello of each node
 }
 
 pub type ZchronodArc = Arc<Zchronod>;
@@ -22,20 +38,222 @@ impl Zchronod {
     pub fn zchronod_factory() -> ZchronodFactory {
         ZchronodFactory::init()
     }
+
+    /// Addresses a direct push fans out to: the configured direct peers and
+    /// every peer of the address book.
+    pub async fn direct_targets(&self) -> Vec<SocketAddr> {
+        let mut targets: Vec<SocketAddr> = self.config.net.direct_peers.iter()
+            .filter_map(|peer| peer.parse().ok())
+            .collect();
+        targets.extend(self.network.peer_addresses().await);
+        targets.sort();
+        targets.dedup();
+        targets
+    }
+
+    /// Return false if the hybrid clock of a peer runs too far ahead of our wall time.
+    pub fn within_drift(&self, clock_info: &ClockInfo) -> bool {
+        let wall = wall_time();
+        if clock_info.hlc.is_ahead_of(wall, self.config.node.max_clock_drift) {
+            warn!("Reject clock of node {}: hybrid clock {} ms ahead of local time",
+                clock_info.node_id, clock_info.hlc.physical - wall);
+            return false;
+        }
+        true
+    }
+
+    /// Delta of `clock` against the oldest recent broadcast clock every live
+    /// peer acked, `clock` is kept as a later base. None when delta encoding
+    /// is off, a live peer acked no recent clock, or the full clock is not larger.
+    pub async fn broadcast_delta(&self, clock: &Clock) -> Option<ClockDelta> {
+        if !self.config.node.clock_delta {
+            return None;
+        }
+        let base = {
+            let mut broadcast_clocks = self.broadcast_clocks.lock().await;
+            let base = self.acked_base(&broadcast_clocks).await;
+            broadcast_clocks.push_back(clock.clone());
+            if broadcast_clocks.len() > DELTA_BASE_HISTORY {
+                broadcast_clocks.pop_front();
+            }
+            base?
+        };
+        let values = clock.delta_from(&base)?;
+        if values.len() >= clock.values().len() {
+            return None;
+        }
+        Some(ClockDelta {
+            base_clock_hash: base.hash().to_vec(),
+            values: values.into_iter().map(|(id, value)| (id, value.into())).collect(),
+        })
+    }
+
+    /// The oldest of `broadcast_clocks` acked by every live peer, None when
+    /// there is no live peer or one acked none of them.
+    async fn acked_base(&self, broadcast_clocks: &VecDeque<Clock>) -> Option<Clock> {
+        let now = tools::helper::get_time_ms();
+        let peers = self.peers.read().await;
+        let mut oldest = None;
+        for peer in peers.values().filter(|peer| peer.is_alive(now, self.config.node.heartbeat_timeout)) {
+            let acked = peer.acked_clock.as_ref()?;
+            let position = broadcast_clocks.iter().position(|clock| clock == acked)?;
+            oldest = Some(cmp::min(oldest.unwrap_or(position), position));
+        }
+        oldest.map(|position| broadcast_clocks[position].clone())
+    }
+
+    /// Record the broadcast clock with hash `clock_hash` as acked by node `node_id`.
+    pub async fn note_delta_ack(&self, node_id: &str, clock_hash: &[u8]) {
+        let broadcast_clocks = self.broadcast_clocks.lock().await;
+        let Some(clock) = broadcast_clocks.iter().find(|clock| clock.hash().as_slice() == clock_hash) else {
+            return;
+        };
+        if let Some(peer) = self.peers.write().await.get_mut(node_id) {
+            peer.acked_clock = Some(clock.clone());
+        }
+    }
+
+    /// Rebuild the clock of an event trigger delta from a recent trigger clock
+    /// of node `node_id`. None when the delta base is none of them.
+    pub async fn resolve_delta(&self, node_id: &str, delta: &ClockDelta) -> Option<Clock> {
+        let trigger_clocks = self.trigger_clocks.read().await;
+        let base = trigger_clocks.get(node_id)?.iter()
+            .find(|clock| clock.hash().as_slice() == delta.base_clock_hash.as_slice())?;
+        let values = delta.values.iter().map(|(id, value)| (id.clone(), u128::from(value))).collect();
+        Some(base.apply_delta(&values))
+    }
+
+    /// Remember the verified event trigger clock of a node as a base of its next deltas.
+    pub async fn note_trigger_clock(&self, clock_info: &ClockInfo) {
+        let mut trigger_clocks = self.trigger_clocks.write().await;
+        let clocks = trigger_clocks.entry(clock_info.node_id.clone()).or_default();
+        clocks.push_back(clock_info.clock.clone());
+        if clocks.len() > DELTA_BASE_HISTORY {
+            clocks.pop_front();
+        }
+    }
+
+    /// Hash of the last event trigger clock received from node `node_id`, empty if none.
+    pub async fn last_trigger_hash(&self, node_id: &str) -> Vec<u8> {
+        let trigger_clocks = self.trigger_clocks.read().await;
+        trigger_clocks.get(node_id).and_then(VecDeque::back).map_or_else(Vec::new, |clock| clock.hash().to_vec())
+    }
+
+    /// Remember a server node we got a verified clock from. A new node is
+    /// recorded at relay address `src` and persisted right away, a known node
+    /// keeps its address: signed clocks can be replayed from anywhere, only a
+    /// fresh hello moves a node, see `move_peer`.
+    pub async fn note_peer(&self, clock_info: &ClockInfo, src: SocketAddr) {
+        if clock_info.node_id == self.node_id {
+            return;
+        }
+        let (address, changed) = {
+            let mut peers = self.peers.write().await;
+            let peer = peers.entry(clock_info.node_id.clone()).or_insert_with(|| PeerInfo::new(src));
+            let changed = peer.last_clock.is_none();
+            peer.last_clock = Some(clock_info.clone());
+            (peer.address, changed.then(|| peer.clone()))
+        };
+        self.network.add_peer(&clock_info.node_id, address).await;
+        self.network.peer_seen(&clock_info.node_id).await;
+        if let Some(peer) = changed {
+            self.storage.sinker_peer(&clock_info.node_id, &peer).await;
+        }
+    }
+
+    /// Move a known node to relay address `src`, after a fresh signed hello from there.
+    pub async fn move_peer(&self, node_id: &str, src: SocketAddr) {
+        let moved = {
+            let mut peers = self.peers.write().await;
+            peers.get_mut(node_id).filter(|peer| peer.address != src).map(|peer| {
+                peer.address = src;
+                peer.clone()
+            })
+        };
+        if let Some(peer) = moved {
+            info!("Node {} moved to {}", node_id, src);
+            self.network.add_peer(node_id, src).await;
+            self.storage.sinker_peer(node_id, &peer).await;
+        }
+    }
+
+    /// Record a heartbeat of a peer and persist its registry entry. Like
+    /// `note_peer`, a known node keeps its address.
+    pub async fn note_heartbeat(&self, clock_info: &ClockInfo, src: SocketAddr) {
+        if clock_info.node_id == self.node_id {
+            return;
+        }
+        let peer = {
+            let mut peers = self.peers.write().await;
+            let peer = peers.entry(clock_info.node_id.clone()).or_insert_with(|| PeerInfo::new(src));
+            peer.last_heartbeat = tools::helper::get_time_ms();
+            peer.last_clock = Some(clock_info.clone());
+            peer.clone()
+        };
+        self.network.add_peer(&clock_info.node_id, peer.address).await;
+        self.network.peer_seen(&clock_info.node_id).await;
+        self.storage.sinker_peer(&clock_info.node_id, &peer).await;
+    }
+}
+
+/// A known server node of the membership registry, the node id is its public key.
+#[derive(Debug, Clone)]
+pub struct PeerInfo {
+    pub address: SocketAddr,            // relay address the node was last seen at
+    pub last_heartbeat: u128,           // milliseconds, 0 before the first heartbeat
+    pub last_clock: Option<ClockInfo>,  // last verified clock of the node
+    pub acked_clock: Option<Clock>,     // last event trigger clock of ours the node acked, not persisted
+}
+
+impl PeerInfo {
+    pub fn new(address: SocketAddr) -> Self {
+        Self { address, last_heartbeat: 0, last_clock: None, acked_clock: None }
+    }
+
+    /// Returns true if the last heartbeat is at most `timeout` milliseconds before `now`.
+    pub fn is_alive(&self, now: u128, timeout: u64) -> bool {
+        self.last_heartbeat > 0 && now.saturating_sub(self.last_heartbeat) <= u128::from(timeout)
+    }
 }
 
 /// A cache state of a server node.
 #[derive(Debug, Clone)]
 pub struct ServerState {
     pub clock_info: ClockInfo,
-    pub message_ids: VecDeque<String>,
+    pub message_ids: VecDeque<(u128, String)>,             // (own event index, message id) in arrival order
     pub cache_items: BTreeMap<String, ZMessage>,
-    pub cache_maximum: u64,
+    pub cache_maximum: u64,     // cache size above which causally stable items are evicted
+    pub peer_clocks: BTreeMap<String, Clock>,               // matrix clock: latest known clock of each peer
+    pub keypair: NodeKeypair,
+    pub pending: BTreeMap<(String, u128), PendingEvent>,    // (sender, sender event index) -> buffered trigger
+    pub pending_maximum: usize,
+    pub pending_timeout: u64,   // milliseconds
+    pub clock_epochs: Vec<BTreeMap<String, u128>>,          // retired node ids -> final count, per agreed epoch
+}
+
+/// An event trigger waiting in the causal buffer for its predecessors.
+#[derive(Debug, Clone)]
+pub struct PendingEvent {
+    pub clock_info: ClockInfo,
+    pub message: ZMessage,
+    pub origin: ClockInfo,
+    pub inner_msg: Innermsg,
+    pub link: Link,            // link the trigger came in on, catch-up sync answers on it
+    pub src: SocketAddr,
+    pub received_at: u128,     // milliseconds
+}
+
+/// A trigger applied to the state, with the state clock right after it.
+#[derive(Debug, Clone)]
+pub struct DeliveredEvent {
+    pub event: PendingEvent,
+    pub added: bool,
+    pub clock_info: ClockInfo,
 }
 
 impl ServerState {
     /// Create a new server state.
-    pub fn new(node_id: String, cache_maximum: u64) -> Self {
+    pub fn new(node_id: String, keypair: NodeKeypair, cache_maximum: u64) -> Self {
         Self {
             clock_info: ClockInfo::new(
                 Clock::new(),
@@ -47,60 +265,397 @@ impl ServerState {
             message_ids: VecDeque::new(),
             cache_items: BTreeMap::new(),
             cache_maximum,
+            peer_clocks: BTreeMap::new(),
+            keypair,
+            pending: BTreeMap::new(),
+            pending_maximum: 0,
+            pending_timeout: 0,
+            clock_epochs: Vec::new(),
         }
     }
 
+    /// Start from a genesis clock of the given kind.
+    pub fn with_clock_kind(mut self, kind: ClockKind) -> Self {
+        self.clock_info.clock = Clock::with_kind(kind);
+        self
+    }
+
+    /// Set the causal buffer limits, a zero maximum disables buffering.
+    pub fn with_pending_limits(mut self, pending_maximum: usize, pending_timeout: u64) -> Self {
+        self.pending_maximum = pending_maximum;
+        self.pending_timeout = pending_timeout;
+        self
+    }
+
+    /// Fold the dimensions of retired nodes as agreed by the clock epochs.
+    pub fn with_clock_epochs(mut self, clock_epochs: Vec<BTreeMap<String, u128>>) -> Self {
+        self.clock_epochs = clock_epochs;
+        self.clock_info.clock.fold_epochs(&self.clock_epochs, self.clock_epochs.len());
+        self
+    }
+
+    /// The local clock and `other` at the same epoch level, the highest both can reach.
+    fn align_with(&self, other: &Clock) -> (Clock, Clock) {
+        let local_level = self.clock_info.clock.epoch_level(&self.clock_epochs);
+        let other = other.align_epochs(&self.clock_epochs, local_level);
+        let local = self.clock_info.clock.align_epochs(&self.clock_epochs, other.epoch_level(&self.clock_epochs));
+        (local, other)
+    }
+
+    /// Compare the local clock with a peer clock folded to another epoch level.
+    pub fn compare(&self, other: &Clock) -> Option<cmp::Ordering> {
+        let (local, other) = self.align_with(other);
+        local.partial_cmp(&other)
+    }
+
     /// Add items into the state. Returns true if resulting in a new state.
+    /// Items already in the cache are skipped, all new items count as one event.
     pub fn add(&mut self, items: Vec<ZMessage>) -> bool {
-        if items.is_empty() {
+        let Some(last_id) = self.add_items(items) else {
             return false;
-        } 
+        };
+        self.clock_info.hlc.tick(wall_time());
+        self.new_event(last_id);
+        true
+    }
 
+    /// Cache the new items, returns the id of the last one added.
+    /// The items become the next own event of this node.
+    fn add_items(&mut self, items: Vec<ZMessage>) -> Option<String> {
+        let mut last_id = None;
+        let event_index = self.clock_info.clock.get(&self.clock_info.node_id) + 1;
         for item in items.iter() {
             // filter replicate message id
             let msg_id = hex::encode(item.id.clone());
             if !self.cache_items.contains_key(&msg_id)  {
-                if self.message_ids.len() > self.cache_maximum.try_into().unwrap() {
-                    let old_id = self.message_ids.pop_front().unwrap_or_default();
-                    self.cache_items.remove(&old_id);
-                }
-                self.message_ids.push_back(msg_id.clone());
-                self.cache_items.insert(msg_id, item.clone());
+                self.message_ids.push_back((event_index, msg_id.clone()));
+                self.cache_items.insert(msg_id.clone(), item.clone());
+                last_id = Some(msg_id);
             } else {
                 info!("duplicate message_id, skip & no action");
                 continue;
             }
         }
+        self.evict_stable();
+        last_id
+    }
 
-        let now = tools::helper::get_time_ms();
-        self.clock_info.clock.inc(self.clock_info.node_id.clone());
-        self.clock_info.count += 1;
-        self.clock_info.create_at = now;
-        if let Some(last) = items.last() {
-            let last_id = hex::encode(last.id.clone());
-            self.clock_info.message_id = last_id;
+    /// Record the clock of a peer from its event trigger, the matrix clock
+    /// row of the peer only moves forward.
+    pub fn observe_peer(&mut self, clock_info: &ClockInfo) {
+        if clock_info.node_id == self.clock_info.node_id {
+            return;
+        }
+        self.peer_clocks.entry(clock_info.node_id.clone())
+            .and_modify(|clock| clock.merge(&[&clock_info.clock]))
+            .or_insert_with(|| clock_info.clock.clone());
+        self.evict_stable();
+    }
 
-            let clock_str = serde_json::to_string(&self.clock_info.clock).unwrap();
-            let hash_hex = sha256_str_to_hex(clock_str.clone());
-            self.clock_info.clock_hash = hash_hex;
+    /// Drop the matrix clock rows of the peers `is_live` rejects, so a dead
+    /// peer no longer holds back the stable frontier. A row comes back with
+    /// the next event trigger of the peer.
+    pub fn retain_peers(&mut self, is_live: impl Fn(&str) -> bool) {
+        self.peer_clocks.retain(|id, _| is_live(id));
+        self.evict_stable();
+    }
+
+    /// Events every known node has seen: the pointwise minimum of the local
+    /// clock and the matrix clock rows. Retired nodes no longer count.
+    pub fn stable_frontier(&self) -> Clock {
+        let level = self.clock_info.clock.epoch_level(&self.clock_epochs);
+        let retired = |id: &String| self.clock_epochs.iter().any(|epoch| epoch.contains_key(id));
+        self.peer_clocks.iter()
+            .filter(|(id, _)| !retired(id))
+            .fold(self.clock_info.clock.clone(), |frontier, (_, clock)| {
+                frontier.base_common(&clock.align_epochs(&self.clock_epochs, level))
+            })
+    }
+
+    /// Evict the oldest cached items every known node has seen while the
+    /// cache is above its maximum. Items not yet stable stay cached up to
+    /// `CACHE_HARD_FACTOR` times the maximum.
+    fn evict_stable(&mut self) {
+        if self.message_ids.len() as u64 <= self.cache_maximum {
+            return;
+        }
+        let stable = self.stable_frontier().get(&self.clock_info.node_id);
+        let hard_maximum = self.cache_maximum.saturating_mul(CACHE_HARD_FACTOR);
+        while self.message_ids.len() as u64 > self.cache_maximum {
+            let over_hard = self.message_ids.len() as u64 > hard_maximum;
+            match self.message_ids.front() {
+                Some((event_index, _)) if *event_index <= stable || over_hard => {
+                    let (_, old_id) = self.message_ids.pop_front().expect("front was just checked");
+                    self.cache_items.remove(&old_id);
+                }
+                _ => break,
+            }
         }
+    }
 
-        true
+    /// Count one own event for message `last_id` and seal the state.
+    fn new_event(&mut self, last_id: String) {
+        self.clock_info.clock.inc(self.clock_info.node_id.clone());
+        self.clock_info.count += 1;
+        self.seal(last_id);
     }
 
-    /// Merge another ServerState into the current state. Returns true if
-    /// resulting in a new state (different from current and received
-    /// state).
-    pub fn merge(&mut self, from_clock: ClockInfo, items: &Vec<ZMessage>) -> (bool, bool) {
-        match self.clock_info.clock.partial_cmp(&from_clock.clock) {
+    /// Returns true if a message carried by `from` can be applied right now:
+    /// it is the next event of the sender and every other event it depends
+    /// on is already known locally.
+    pub fn is_deliverable(&self, from: &ClockInfo) -> bool {
+        let (local, from_clock) = self.align_with(&from.clock);
+        from_clock.get(&from.node_id) == local.get(&from.node_id) + 1
+            && from_clock.values().iter().all(|(id, value)| id == &from.node_id || *value <= local.get(id))
+    }
+
+    /// Merge another ServerState into the current state. Returns
+    /// (new items added, state changed). The caller must make sure that
+    /// every event covered by `from_clock` is in `items` or already known.
+    pub fn merge(&mut self, from_clock: ClockInfo, items: &[ZMessage]) -> (bool, bool) {
+        let (mut local, from) = self.align_with(&from_clock.clock);
+        match local.partial_cmp(&from) {
             Some(cmp::Ordering::Equal) => (false, false),
             Some(cmp::Ordering::Greater) => (false, false),
             Some(cmp::Ordering::Less) | None => {
-                // todo: can merge when just one event last
-                self.clock_info.clock.merge(&vec![&from_clock.clock]);
-                let added = self.add(items.to_vec());
-                (added, added)
+                local.merge(&[&from]);
+                self.clock_info.clock = local;
+                self.clock_info.hlc.observe(&from_clock.hlc, wall_time());
+                if let Some(last_id) = self.add_items(items.to_vec()) {
+                    self.new_event(last_id);
+                    (true, true)
+                } else {
+                    // only knowledge of events we already hold, not an event of this node
+                    self.seal(String::new());
+                    (false, true)
+                }
+            }
+        }
+    }
+
+    /// Apply a deliverable trigger. Returns None if the state didn't change.
+    pub fn deliver(&mut self, event: PendingEvent) -> Option<DeliveredEvent> {
+        let (added, merged) = self.merge(event.clock_info.clone(), std::slice::from_ref(&event.message));
+        merged.then(|| DeliveredEvent { event, added, clock_info: self.clock_info.clone() })
+    }
+
+    /// Hold a trigger until its causal predecessors are applied. Returns false
+    /// if the buffer is full, the caller then has to rely on catch-up sync.
+    pub fn buffer(&mut self, event: PendingEvent) -> bool {
+        let sender_index = event.clock_info.clock.get(&event.clock_info.node_id);
+        let key = (event.clock_info.node_id.clone(), sender_index);
+        if !self.pending.contains_key(&key) && self.pending.len() >= self.pending_maximum {
+            return false;
+        }
+        self.pending.insert(key, event);
+        true
+    }
+
+    /// Apply every buffered trigger that became deliverable, in causal order,
+    /// and drop the ones already covered by the local clock.
+    pub fn deliver_pending(&mut self) -> Vec<DeliveredEvent> {
+        let mut delivered = Vec::new();
+        loop {
+            let covered: Vec<(String, u128)> = self.pending.iter()
+                .filter(|(_, event)| matches!(self.compare(&event.clock_info.clock), Some(cmp::Ordering::Equal) | Some(cmp::Ordering::Greater)))
+                .map(|(key, _)| key.clone())
+                .collect();
+            for key in covered {
+                self.pending.remove(&key);
             }
+            let Some(key) = self.pending.iter()
+                .find(|(_, event)| self.is_deliverable(&event.clock_info))
+                .map(|(key, _)| key.clone()) else {
+                break;
+            };
+            let event = self.pending.remove(&key).expect("key was just found");
+            delivered.extend(self.deliver(event));
         }
+        delivered
+    }
+
+    /// Remove and return the triggers buffered longer than the pending timeout.
+    pub fn expire_pending(&mut self, now: u128) -> Vec<PendingEvent> {
+        let timeout = u128::from(self.pending_timeout);
+        let expired: Vec<(String, u128)> = self.pending.iter()
+            .filter(|(_, event)| now.saturating_sub(event.received_at) > timeout)
+            .map(|(key, _)| key.clone())
+            .collect();
+        expired.into_iter().filter_map(|key| self.pending.remove(&key)).collect()
+    }
+
+    /// Record a new clock state: fold the retired dimensions the clock
+    /// completed, chain it to the previous hash and sign it.
+    fn seal(&mut self, message_id: String) {
+        self.clock_info.clock.fold_epochs(&self.clock_epochs, self.clock_epochs.len());
+        self.clock_info.message_id = message_id;
+        self.clock_info.create_at = tools::helper::get_time_ms();
+        self.clock_info.prev_clock_hash = std::mem::take(&mut self.clock_info.clock_hash);
+        self.clock_info.clock_hash = self.clock_info.clock.hash_hex();
+        self.clock_info.sign(&self.keypair);
     }
-}
\ No newline at end of file
+}
+
+/// Local wall time in milliseconds for the hybrid logical clock.
+fn wall_time() -> u64 {
+    tools::helper::get_time_ms().try_into().unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tools::crypto::{keypair_from_hex, node_id_of};
+
+    fn new_state(secret_key: &str) -> ServerState {
+        let keypair = keypair_from_hex(secret_key).unwrap();
+        ServerState::new(node_id_of(&keypair), keypair, 100).with_pending_limits(10, 1000)
+    }
+
+    fn pending(clock_info: &ClockInfo, message: ZMessage, received_at: u128) -> PendingEvent {
+        PendingEvent {
+            clock_info: clock_info.clone(),
+            message,
+            origin: clock_info.clone(),
+            inner_msg: Innermsg::default(),
+            link: Link::Inner,
+            src: "127.0.0.1:8050".parse().unwrap(),
+            received_at,
+        }
+    }
+
+    fn message(id: u8) -> ZMessage {
+        ZMessage { id: vec![id; 32], ..Default::default() }
+    }
+
+    #[test]
+    fn add_skips_duplicates() {
+        let mut state = new_state("5b3e8f2d1c7a4b6e9f0d2c8a7b5e3f1d4c6a8b0e2f4d6c8a1b3e5f7d9c0a2b4e");
+        assert!(state.add(vec![message(1)]));
+        let hash = state.clock_info.clock_hash.clone();
+        assert!(!state.add(vec![message(1)]));
+        assert_eq!(state.clock_info.count, 1);
+        assert_eq!(state.clock_info.clock_hash, hash);
+        assert!(state.clock_info.verify_signature());
+    }
+
+    #[test]
+    fn merge_deliverable_events() {
+        let mut a = new_state("5b3e8f2d1c7a4b6e9f0d2c8a7b5e3f1d4c6a8b0e2f4d6c8a1b3e5f7d9c0a2b4e");
+        let mut b = new_state("e1a7c9d3b5f2a8c4e6d0b9f7a3c5e1d8b2f4a6c0e9d7b3f5a1c8e2d4b6f0a9c3");
+
+        a.add(vec![message(1)]);
+        let first = a.clock_info.clone();
+        a.add(vec![message(2)]);
+        let second = a.clock_info.clone();
+
+        // the second event of a depends on the first one
+        assert!(!b.is_deliverable(&second));
+        assert!(b.is_deliverable(&first));
+        assert_eq!(b.merge(first, &[message(1)]), (true, true));
+        assert!(b.is_deliverable(&second));
+        assert_eq!(b.merge(second.clone(), &[message(2)]), (true, true));
+        assert!(b.clock_info.hlc > second.hlc);
+
+        // a learns that b holds both messages, no new event on a
+        let count = a.clock_info.count;
+        assert_eq!(a.merge(b.clock_info.clone(), &[message(2)]), (false, true));
+        assert_eq!(a.clock_info.count, count);
+        assert!(a.clock_info.message_id.is_empty());
+        assert!(a.clock_info.verify_signature());
+        assert_eq!(a.merge(second, &[message(2)]), (false, false));
+    }
+
+    #[test]
+    fn buffer_until_predecessors_applied() {
+        let mut a = new_state("5b3e8f2d1c7a4b6e9f0d2c8a7b5e3f1d4c6a8b0e2f4d6c8a1b3e5f7d9c0a2b4e");
+        let mut b = new_state("e1a7c9d3b5f2a8c4e6d0b9f7a3c5e1d8b2f4a6c0e9d7b3f5a1c8e2d4b6f0a9c3");
+
+        a.add(vec![message(1)]);
+        let first = a.clock_info.clone();
+        a.add(vec![message(2)]);
+        let second = a.clock_info.clone();
+
+        // the second event arrives first and waits for the first one
+        assert!(b.buffer(pending(&second, message(2), 0)));
+        assert!(b.deliver_pending().is_empty());
+        assert!(b.buffer(pending(&first, message(1), 0)));
+        let delivered = b.deliver_pending();
+        assert_eq!(delivered.len(), 2);
+        assert_eq!(delivered[0].event.message.id, message(1).id);
+        assert_eq!(delivered[1].event.message.id, message(2).id);
+        assert!(delivered.iter().all(|d| d.added));
+        assert!(b.pending.is_empty());
+        assert_eq!(b.clock_info.count, 2);
+    }
+
+    #[test]
+    fn buffer_limits_and_timeout() {
+        let mut a = new_state("5b3e8f2d1c7a4b6e9f0d2c8a7b5e3f1d4c6a8b0e2f4d6c8a1b3e5f7d9c0a2b4e");
+        let mut b = new_state("e1a7c9d3b5f2a8c4e6d0b9f7a3c5e1d8b2f4a6c0e9d7b3f5a1c8e2d4b6f0a9c3").with_pending_limits(1, 1000);
+
+        a.add(vec![message(1)]);
+        a.add(vec![message(2)]);
+        let second = a.clock_info.clone();
+        a.add(vec![message(3)]);
+        let third = a.clock_info.clone();
+
+        assert!(b.buffer(pending(&second, message(2), 0)));
+        assert!(!b.buffer(pending(&third, message(3), 0)));
+        assert!(b.expire_pending(1000).is_empty());
+        assert_eq!(b.expire_pending(1001).len(), 1);
+        assert!(b.pending.is_empty());
+    }
+
+    #[test]
+    fn evict_causally_stable_items() {
+        let mut a = new_state("5b3e8f2d1c7a4b6e9f0d2c8a7b5e3f1d4c6a8b0e2f4d6c8a1b3e5f7d9c0a2b4e");
+        let mut b = new_state("e1a7c9d3b5f2a8c4e6d0b9f7a3c5e1d8b2f4a6c0e9d7b3f5a1c8e2d4b6f0a9c3");
+        a.cache_maximum = 1;
+
+        a.add(vec![message(1)]);
+        b.merge(a.clock_info.clone(), &[message(1)]);
+        a.observe_peer(&b.clock_info);
+        a.add(vec![message(2)]);
+        a.add(vec![message(3)]);
+
+        // b has only seen the first event of a
+        assert_eq!(a.stable_frontier().get(&a.clock_info.node_id), 1);
+        assert_eq!(a.message_ids.len(), 2);
+        assert!(!a.cache_items.contains_key(&hex::encode(message(1).id)));
+
+        b.merge(a.clock_info.clone(), &[message(2), message(3)]);
+        a.observe_peer(&b.clock_info);
+        assert_eq!(a.stable_frontier().get(&a.clock_info.node_id), 3);
+        assert_eq!(a.message_ids.len(), 1);
+        assert!(a.cache_items.contains_key(&hex::encode(message(3).id)));
+    }
+
+    #[test]
+    fn evict_without_dead_peers() {
+        let mut a = new_state("5b3e8f2d1c7a4b6e9f0d2c8a7b5e3f1d4c6a8b0e2f4d6c8a1b3e5f7d9c0a2b4e");
+        let b = new_state("e1a7c9d3b5f2a8c4e6d0b9f7a3c5e1d8b2f4a6c0e9d7b3f5a1c8e2d4b6f0a9c3");
+        a.cache_maximum = 1;
+        a.observe_peer(&b.clock_info);
+        for i in 1..=5 {
+            a.add(vec![message(i)]);
+        }
+
+        // b has seen nothing, the hard cap bounds the cache
+        assert_eq!(a.stable_frontier().get(&a.clock_info.node_id), 0);
+        assert_eq!(a.message_ids.len() as u64, CACHE_HARD_FACTOR);
+
+        // once b is dead it no longer holds back the frontier
+        a.retain_peers(|id| id != b.clock_info.node_id);
+        assert_eq!(a.stable_frontier().get(&a.clock_info.node_id), 5);
+        assert_eq!(a.message_ids.len(), 1);
+    }
+
+    #[test]
+    fn peer_liveness() {
+        let mut peer = PeerInfo::new("127.0.0.1:8050".parse().unwrap());
+        assert!(!peer.is_alive(1000, 500));
+        peer.last_heartbeat = 1000;
+        assert!(peer.is_alive(1500, 500));
+        assert!(!peer.is_alive(1501, 500));
+    }
+}